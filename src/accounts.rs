@@ -0,0 +1,118 @@
+use std::sync::Arc;
+use std::time::Duration;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use service::account::{Session, UserId};
+use service::ServiceError;
+use crate::storage::{now_ms, storage_error, Storage};
+use crate::workers::BlockingPool;
+
+/// How long a session token stays valid.
+const SESSION_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+const MAX_USERNAME_LEN: usize = 32;
+const MIN_PASSWORD_LEN: usize = 8;
+const MAX_PASSWORD_LEN: usize = 128;
+
+fn validate(username: &str, password: &str) -> Result<(), ServiceError> {
+    if username.is_empty() || username.chars().count() > MAX_USERNAME_LEN || username.contains(char::is_whitespace) {
+        return Err(ServiceError::InvalidCredentials(format!(
+            "el usuario debe tener entre 1 y {MAX_USERNAME_LEN} caracteres, sin espacios"
+        )));
+    }
+    if !(MIN_PASSWORD_LEN..=MAX_PASSWORD_LEN).contains(&password.chars().count()) {
+        return Err(ServiceError::InvalidCredentials(format!(
+            "la contraseña debe tener entre {MIN_PASSWORD_LEN} y {MAX_PASSWORD_LEN} caracteres"
+        )));
+    }
+    Ok(())
+}
+
+fn hash_password(password: &str) -> Result<String, ServiceError> {
+    let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>())
+        .map_err(|e| ServiceError::Storage(e.to_string()))?;
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| ServiceError::Storage(e.to_string()))
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+}
+
+fn new_token() -> String {
+    rand::random::<[u8; 32]>().iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Player accounts. Passwords are stored as argon2 hashes; hashing runs on `hashers` because it is
+/// deliberately slow, and storage is used from the threads of `io`, like [`crate::games::Games`]
+/// does.
+pub struct Accounts {
+    storage: Arc<dyn Storage>,
+    io: BlockingPool,
+    hashers: BlockingPool,
+    /// A hash no password matches, checked against when the user does not exist so that a failed
+    /// login takes as long either way and does not tell which usernames are taken. Built here,
+    /// before any login, so that not even the first one takes longer.
+    dummy_hash: Arc<str>,
+}
+
+impl Accounts {
+    pub fn new(storage: Arc<dyn Storage>, io: BlockingPool, hashers: BlockingPool) -> Self {
+        let dummy_hash = hash_password(&new_token()).unwrap_or_default().into();
+        Accounts { storage, io, hashers, dummy_hash }
+    }
+
+    pub async fn register(&self, username: String, password: String) -> Result<Session, ServiceError> {
+        validate(&username, &password)?;
+        let hash = self.hashers.run(move || hash_password(&password)).await?;
+
+        let name = username.clone();
+        match self.stored(move |storage| storage.create_user(&name, &hash)).await? {
+            Some(user_id) => self.open_session(user_id, username).await,
+            None => Err(ServiceError::UsernameTaken(username)),
+        }
+    }
+
+    pub async fn login(&self, username: String, password: String) -> Result<Session, ServiceError> {
+        let name = username.clone();
+        let (user_id, hash) = self.stored(move |storage| storage.find_user(&name)).await?.unzip();
+
+        let dummy_hash = self.dummy_hash.clone();
+        let verified = self.hashers.run(move || match &hash {
+            Some(hash) => verify_password(&password, hash),
+            None => verify_password(&password, &dummy_hash),
+        }).await;
+        match user_id {
+            Some(user_id) if verified => self.open_session(user_id, username).await,
+            _ => Err(ServiceError::LoginFailed),
+        }
+    }
+
+    /// The session `token` belongs to.
+    pub async fn authenticate(&self, token: String) -> Result<Session, ServiceError> {
+        let (found, token) = self.stored(move |storage| Ok((storage.session_user(&token, now_ms())?, token))).await?;
+        match found {
+            Some((user_id, username)) => Ok(Session { user_id, username, token }),
+            None => Err(ServiceError::InvalidSession),
+        }
+    }
+
+    async fn open_session(&self, user_id: UserId, username: String) -> Result<Session, ServiceError> {
+        let token = new_token();
+        let expires_at = now_ms() + SESSION_TTL.as_millis() as u64;
+        let token = self.stored(move |storage| storage.create_session(&token, user_id, expires_at).map(|()| token)).await?;
+        Ok(Session { user_id, username, token })
+    }
+
+    /// Runs `job` against the storage on one of the `io` threads.
+    async fn stored<T, F>(&self, job: F) -> Result<T, ServiceError>
+    where
+        F: FnOnce(&dyn Storage) -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let storage = self.storage.clone();
+        self.io.run(move || job(storage.as_ref())).await.map_err(storage_error)
+    }
+}
@@ -1,47 +1,244 @@
-use service::{WorldClient, sudoku::SudokuSize};
-use tarpc::{client, context, tokio_serde::formats::Json};
+use service::{ServiceError, WorldClient, sudoku::SudokuSize};
+use tarpc::{client::{self, RpcError}, context, tokio_serde::formats::Json};
 use std::net::SocketAddr;
-use service::sudoku::{Sudoku};
+use anyhow::Context;
+use std::time::{Duration, Instant};
+use service::account::Session;
+use service::game::{Game, GameId, SolveRecord, Streak};
+use service::event::Event;
+use service::replay::Replay;
+use service::race::{RaceStatus, RoomId};
+use service::sudoku::Difficulty;
 
+/// How long the client waits for each kind of call before giving up. The deadline travels with
+/// the request, so the server stops working on it too.
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    pub request: Duration,
+    /// Puzzle generation, which can take a while for 16x16.
+    pub generate: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            request: Duration::from_secs(10),
+            generate: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Who the client logs in as when it connects.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// Token from an earlier login; preferred over username and password.
+    pub session_token: Option<String>,
+}
+
+#[derive(Clone)]
 pub struct RPCClient {
-    rpc: WorldClient
+    rpc: WorldClient,
+    timeouts: Timeouts,
 }
 
 impl RPCClient {
-    pub async fn new(addr: SocketAddr) -> Self {
+    /// Connects to the server at `addr`.
+    pub async fn new(addr: SocketAddr, timeouts: Timeouts) -> anyhow::Result<Self> {
         let mut transport = tarpc::serde_transport::tcp::connect(addr, Json::default);
         transport.config_mut().max_frame_length(usize::MAX);
-        let client = WorldClient::new(client::Config::default(), transport.await.unwrap()).spawn();
+        let transport = transport.await.with_context(|| format!("no se pudo conectar con el servidor {addr}"))?;
+        let client = WorldClient::new(client::Config::default(), transport).spawn();
         tracing::info!(target: "cliente", "Cliente inicializado");
-        Self {
-            rpc: client
+        Ok(Self {
+            rpc: client,
+            timeouts,
+        })
+    }
+
+    fn context(timeout: Duration) -> context::Context {
+        let mut ctx = context::current();
+        ctx.deadline = Instant::now() + timeout;
+        ctx
+    }
+
+    /// Starts a game on a new puzzle of `size`.
+    pub async fn start_game(&self, size: SudokuSize, difficulty: Difficulty) -> anyhow::Result<Game> {
+        let game = flatten(self.rpc.start(Self::context(self.timeouts.generate), size, difficulty).await)?;
+        let mut buffer = String::new();
+        for row in &game.sudoku.board {
+            buffer.push_str(&format!("{row:?}\n"));
         }
+        tracing::info!(target: "cliente", "PARTIDA {}: {}", game.id, buffer);
+        Ok(game)
     }
 
-    pub async fn sudoku4x4(&self) -> Sudoku {
-        self.request_sudoku(SudokuSize::SUDOKU4X4).await.unwrap()
+    pub async fn resume_game(&self, game_id: GameId) -> anyhow::Result<Game> {
+        let game = flatten(self.rpc.resume(Self::context(self.timeouts.request), game_id).await)?;
+        tracing::info!(target: "cliente", "Partida {game_id} reanudada");
+        Ok(game)
     }
 
-    pub async fn sudoku9x9(&self) -> Sudoku {
-        self.request_sudoku(SudokuSize::SUDOKU9X9).await.unwrap()
+    /// Writes `value` (0 clears) in `game`, replacing it with the server's copy once accepted.
+    pub async fn place(&self, game: &mut Game, row: u8, col: u8, value: u8) -> anyhow::Result<()> {
+        *game = flatten(self.rpc.place(Self::context(self.timeouts.request), game.id, row, col, value).await)?;
+        Ok(())
     }
 
-    pub async fn sudoku16x16(&self) -> Sudoku {
-        self.request_sudoku(SudokuSize::SUDOKU16X16).await.unwrap()
+    /// Takes back the last move in `game`, replacing it with the server's copy.
+    pub async fn undo(&self, game: &mut Game) -> anyhow::Result<()> {
+        *game = flatten(self.rpc.undo(Self::context(self.timeouts.request), game.id).await)?;
+        Ok(())
     }
 
-    async fn request_sudoku(&self, size: SudokuSize, ) -> Result<Sudoku, anyhow::Error> {
-        let sudoku = self.rpc.sudoku(context::current(), size).await?.unwrap();
-        let mut buffer = String::new();
-        for row in &sudoku.board {
-            buffer.push_str(&format!("{row:?}\n"));
+    /// Plays again the last undone move in `game`, replacing it with the server's copy.
+    pub async fn redo(&self, game: &mut Game) -> anyhow::Result<()> {
+        *game = flatten(self.rpc.redo(Self::context(self.timeouts.request), game.id).await)?;
+        Ok(())
+    }
+
+    /// Stops the clock of `game`, or starts it again if `paused` is false.
+    pub async fn pause(&self, game: &mut Game, paused: bool) -> anyhow::Result<()> {
+        *game = flatten(self.rpc.pause(Self::context(self.timeouts.request), game.id, paused).await)?;
+        Ok(())
+    }
+
+    /// Adds `value` to the notes of a cell of `game` or takes it out (0 clears them), replacing
+    /// `game` with the server's copy.
+    pub async fn note(&self, game: &mut Game, row: u8, col: u8, value: u8) -> anyhow::Result<()> {
+        *game = flatten(self.rpc.note(Self::context(self.timeouts.request), game.id, row, col, value).await)?;
+        Ok(())
+    }
+
+    pub async fn leaderboard(&self, size: SudokuSize, difficulty: Difficulty, limit: u32) -> anyhow::Result<Vec<SolveRecord>> {
+        flatten(self.rpc.leaderboard(Self::context(self.timeouts.request), size, difficulty, limit).await)
+    }
+
+    pub async fn personal_best(&self, size: SudokuSize, difficulty: Difficulty) -> anyhow::Result<Option<SolveRecord>> {
+        flatten(self.rpc.personal_best(Self::context(self.timeouts.request), size, difficulty).await)
+    }
+
+    /// Starts, or picks up again, today's daily puzzle of `size`.
+    pub async fn daily(&self, size: SudokuSize) -> anyhow::Result<Game> {
+        let game = flatten(self.rpc.daily(Self::context(self.timeouts.generate), size).await)?;
+        tracing::info!(target: "cliente", "Sudoku del día, partida {}", game.id);
+        Ok(game)
+    }
+
+    pub async fn daily_leaderboard(&self, size: SudokuSize, limit: u32) -> anyhow::Result<Vec<SolveRecord>> {
+        flatten(self.rpc.daily_leaderboard(Self::context(self.timeouts.request), size, limit).await)
+    }
+
+    pub async fn streak(&self) -> anyhow::Result<Streak> {
+        flatten(self.rpc.streak(Self::context(self.timeouts.request)).await)
+    }
+
+    /// Starts a cooperative game on a new puzzle of `size`.
+    pub async fn start_coop(&self, size: SudokuSize, difficulty: Difficulty) -> anyhow::Result<Game> {
+        let game = flatten(self.rpc.start_coop(Self::context(self.timeouts.generate), size, difficulty).await)?;
+        tracing::info!(target: "cliente", "Partida cooperativa {} creada", game.id);
+        Ok(game)
+    }
+
+    pub async fn join_coop(&self, game_id: GameId) -> anyhow::Result<Game> {
+        let game = flatten(self.rpc.join_coop(Self::context(self.timeouts.request), game_id).await)?;
+        tracing::info!(target: "cliente", "Dentro de la partida cooperativa {game_id}");
+        Ok(game)
+    }
+
+    /// Watches game `game_id` read-only; its events can be followed with `next_events`.
+    pub async fn spectate(&self, game_id: GameId) -> anyhow::Result<Game> {
+        let game = flatten(self.rpc.spectate(Self::context(self.timeouts.request), game_id).await)?;
+        tracing::info!(target: "cliente", "Mirando la partida {game_id}");
+        Ok(game)
+    }
+
+    pub async fn replay(&self, game_id: GameId) -> anyhow::Result<Replay> {
+        let replay = flatten(self.rpc.replay(Self::context(self.timeouts.request), game_id).await)?;
+        tracing::info!(target: "cliente", "Repetición de la partida {game_id}: {} jugadas", replay.moves.len());
+        Ok(replay)
+    }
+
+    /// Long-polls the game's events after `since`: returns as soon as there is one, or empty after
+    /// about one request timeout.
+    pub async fn next_events(&self, game_id: GameId, since: u64) -> anyhow::Result<Vec<Event>> {
+        flatten(self.rpc.next_events(Self::context(self.timeouts.request), game_id, since).await)
+    }
+
+    pub async fn chat(&self, game_id: GameId, text: String) -> anyhow::Result<()> {
+        flatten(self.rpc.chat(Self::context(self.timeouts.request), game_id, text).await)
+    }
+
+    pub async fn create_room(&self, size: SudokuSize, difficulty: Difficulty) -> anyhow::Result<RaceStatus> {
+        let status = flatten(self.rpc.create_room(Self::context(self.timeouts.generate), size, difficulty).await)?;
+        tracing::info!(target: "cliente", "Sala {} creada", status.room);
+        Ok(status)
+    }
+
+    pub async fn join_room(&self, room: RoomId) -> anyhow::Result<RaceStatus> {
+        let status = flatten(self.rpc.join_room(Self::context(self.timeouts.request), room).await)?;
+        tracing::info!(target: "cliente", "Dentro de la sala {room}");
+        Ok(status)
+    }
+
+    pub async fn start_race(&self, room: RoomId) -> anyhow::Result<RaceStatus> {
+        flatten(self.rpc.start_race(Self::context(self.timeouts.request), room).await)
+    }
+
+    /// Long-polls the room: returns when its version passes `since`, or unchanged after about
+    /// one request timeout.
+    pub async fn race_status(&self, room: RoomId, since: u64) -> anyhow::Result<RaceStatus> {
+        flatten(self.rpc.race_status(Self::context(self.timeouts.request), room, since).await)
+    }
+
+    pub async fn register(&self, username: String, password: String) -> anyhow::Result<Session> {
+        let session = flatten(self.rpc.register(Self::context(self.timeouts.request), username, password).await)?;
+        tracing::info!(target: "cliente", "Usuario {} registrado", session.username);
+        Ok(session)
+    }
+
+    pub async fn login(&self, username: String, password: String) -> anyhow::Result<Session> {
+        let session = flatten(self.rpc.login(Self::context(self.timeouts.request), username, password).await)?;
+        tracing::info!(target: "cliente", "Sesión iniciada como {}", session.username);
+        Ok(session)
+    }
+
+    pub async fn authenticate(&self, token: String) -> anyhow::Result<Session> {
+        let session = flatten(self.rpc.authenticate(Self::context(self.timeouts.request), token).await)?;
+        tracing::info!(target: "cliente", "Sesión reanudada como {}", session.username);
+        Ok(session)
+    }
+
+    /// Logs in with the configured session token, or else with username and password. Stays
+    /// anonymous if neither is configured.
+    pub async fn sign_in(&self, credentials: &Credentials) -> anyhow::Result<Option<Session>> {
+        match credentials {
+            Credentials { session_token: Some(token), .. } => Ok(Some(self.authenticate(token.clone()).await?)),
+            Credentials { user: Some(user), password: Some(password), .. } => {
+                Ok(Some(self.login(user.clone(), password.clone()).await?))
+            }
+            Credentials { user: Some(user), password: None, .. } => anyhow::bail!("falta la contraseña de {user}"),
+            _ => Ok(None),
         }
-        tracing::info!(target: "cliente", "SUDOKU: {}", buffer);
-        Ok(sudoku)
     }
 
-    pub async fn check_sudoku(&self, sudoku: &mut Sudoku) {
-        let state = self.rpc.is_solved(context::current(), sudoku.clone()).await;
-        sudoku.state = state.expect("REASON")
+    pub async fn shutdown_server(&self, admin_token: String) -> anyhow::Result<()> {
+        flatten(self.rpc.shutdown(Self::context(self.timeouts.request), admin_token).await)?;
+        tracing::info!(target: "cliente", "Servidor apagándose");
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Merges the transport and service errors of one call, reporting an expired deadline as
+/// [`ServiceError::Timeout`] like the server does.
+fn flatten<T>(result: Result<Result<T, ServiceError>, RpcError>) -> anyhow::Result<T> {
+    match result {
+        Ok(result) => Ok(result?),
+        Err(RpcError::DeadlineExceeded) => {
+            tracing::warn!(target: "cliente", "Petición sin respuesta dentro del plazo");
+            Err(ServiceError::Timeout.into())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
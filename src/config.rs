@@ -0,0 +1,325 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use clap::{Parser, Subcommand};
+use serde::Deserialize;
+use service::game::GameId;
+use service::sudoku::{Difficulty, SudokuSize};
+use crate::client::{Credentials, Timeouts};
+
+/// Puerto por defecto del servidor.
+pub const DEFAULT_PORT: u16 = 2001;
+
+/// Segundos que el servidor espera a las peticiones en curso al apagarse.
+pub const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 10;
+
+/// Límites de conexiones por defecto.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 100;
+pub const DEFAULT_MAX_CONNECTIONS_PER_IP: usize = 8;
+pub const DEFAULT_MAX_REQUESTS_PER_CHANNEL: usize = 16;
+
+/// Generaciones por cliente: ráfaga y recarga por minuto.
+pub const DEFAULT_RATE_LIMIT_BURST: u32 = 10;
+pub const DEFAULT_RATE_LIMIT_PER_MINUTE: u32 = 30;
+
+/// Sudokus preparados por tamaño y dificultad, y cuántos se generan por minuto para reponerlos.
+pub const DEFAULT_PUZZLE_POOL_DEPTH: usize = 3;
+pub const DEFAULT_PUZZLE_POOL_REFILL_PER_MINUTE: u32 = 60;
+
+/// Base de datos SQLite donde el servidor guarda sudokus y partidas.
+pub const DEFAULT_DATABASE: &str = "sudoku.db";
+
+/// Fichero de configuración que se busca en el directorio actual si no se indica `--config`.
+pub const DEFAULT_CONFIG_FILE: &str = "sudoku.toml";
+
+/// Command line flags. Every flag can also be set through its environment variable; values
+/// given here take precedence over the config file.
+#[derive(Debug, Parser)]
+#[command(name = "app", about = "Sudoku generado y validado por un servidor tarpc")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Path to a TOML config file.
+    #[arg(long, global = true, env = "SUDOKU_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Address the server binds to, IPv4 or IPv6 (`0.0.0.0` or `::` for every interface).
+    #[arg(long, global = true, env = "SUDOKU_BIND")]
+    pub bind: Option<IpAddr>,
+
+    /// Port the server listens on. `0` lets the OS pick a free port.
+    #[arg(long, global = true, env = "SUDOKU_PORT")]
+    pub port: Option<u16>,
+
+    /// Address of the server the client connects to. Without it, `tui` starts its own server.
+    #[arg(long, global = true, env = "SUDOKU_CONNECT")]
+    pub connect: Option<SocketAddr>,
+
+    /// Token required by the `shutdown` admin RPC. Without it the server refuses remote shutdowns.
+    #[arg(long, global = true, env = "SUDOKU_ADMIN_TOKEN")]
+    pub admin_token: Option<String>,
+
+    /// Seconds the server waits for in-flight requests when shutting down.
+    #[arg(long, global = true, env = "SUDOKU_DRAIN_TIMEOUT")]
+    pub drain_timeout: Option<u64>,
+
+    /// Maximum number of connections the server serves at once.
+    #[arg(long, global = true, env = "SUDOKU_MAX_CONNECTIONS")]
+    pub max_connections: Option<usize>,
+
+    /// Maximum number of connections from a single IP address.
+    #[arg(long, global = true, env = "SUDOKU_MAX_CONNECTIONS_PER_IP")]
+    pub max_connections_per_ip: Option<usize>,
+
+    /// Maximum number of requests in flight on one connection.
+    #[arg(long, global = true, env = "SUDOKU_MAX_REQUESTS_PER_CHANNEL")]
+    pub max_requests_per_channel: Option<usize>,
+
+    /// Puzzles a client may generate in a burst.
+    #[arg(long, global = true, env = "SUDOKU_RATE_LIMIT_BURST")]
+    pub rate_limit_burst: Option<u32>,
+
+    /// Puzzles per minute a client's allowance refills at.
+    #[arg(long, global = true, env = "SUDOKU_RATE_LIMIT_PER_MINUTE")]
+    pub rate_limit_per_minute: Option<u32>,
+
+    /// Threads used to generate puzzles. Defaults to the number of CPUs.
+    #[arg(long, global = true, env = "SUDOKU_GENERATION_WORKERS")]
+    pub generation_workers: Option<usize>,
+
+    /// Puzzles kept ready per size and difficulty. `0` disables the pool.
+    #[arg(long, global = true, env = "SUDOKU_PUZZLE_POOL_DEPTH")]
+    pub puzzle_pool_depth: Option<usize>,
+
+    /// Puzzles per minute generated to refill the pool.
+    #[arg(long, global = true, env = "SUDOKU_PUZZLE_POOL_REFILL_PER_MINUTE")]
+    pub puzzle_pool_refill_per_minute: Option<u32>,
+
+    /// SQLite file where the server keeps puzzles and games. Created if missing.
+    #[arg(long, global = true, env = "SUDOKU_DATABASE")]
+    pub database: Option<PathBuf>,
+
+    /// Player the client logs in as.
+    #[arg(long, global = true, env = "SUDOKU_USER")]
+    pub user: Option<String>,
+
+    /// Password for `--user`. Prefer the environment variable so it does not show up in `ps`.
+    #[arg(long, global = true, env = "SUDOKU_PASSWORD", hide_env_values = true)]
+    pub password: Option<String>,
+
+    /// Session token from an earlier login, used instead of `--user` and `--password`.
+    #[arg(long, global = true, env = "SUDOKU_SESSION", hide_env_values = true)]
+    pub session: Option<String>,
+
+    /// Seconds the client waits for an ordinary request.
+    #[arg(long, global = true, env = "SUDOKU_REQUEST_TIMEOUT")]
+    pub request_timeout: Option<u64>,
+
+    /// Seconds the client waits for a new puzzle.
+    #[arg(long, global = true, env = "SUDOKU_GENERATE_TIMEOUT")]
+    pub generate_timeout: Option<u64>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run only the server, without a terminal UI.
+    Server,
+    /// Run the terminal UI (the default when no subcommand is given).
+    Tui {
+        /// Reopen a game started earlier, by id.
+        #[arg(long)]
+        resume: Option<GameId>,
+        /// Open the replay viewer on a file written by `replay`.
+        #[arg(long, conflicts_with = "resume")]
+        replay: Option<PathBuf>,
+    },
+    /// Play from a plain line-based prompt.
+    PlayCli,
+    /// Generate a puzzle locally and print it.
+    Generate {
+        #[arg(long, value_enum, default_value = "9x9")]
+        size: SudokuSize,
+        #[arg(long, value_enum, default_value = "medium")]
+        difficulty: Difficulty,
+        /// Also print the solution.
+        #[arg(long)]
+        solution: bool,
+    },
+    /// Solve the puzzle read from FILE (or stdin) and print the solution.
+    Solve {
+        file: Option<PathBuf>,
+    },
+    /// Export the replay of a game: its puzzle and every move with its time and player.
+    Replay {
+        game: GameId,
+        /// Write it to FILE instead of stdout.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Ask the server at `--connect` to shut down, authenticating with `--admin-token`.
+    Shutdown,
+}
+
+/// Contenido del fichero TOML. Todas las secciones y claves son opcionales.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    server: FileServerConfig,
+    client: FileClientConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileServerConfig {
+    bind: Option<IpAddr>,
+    port: Option<u16>,
+    admin_token: Option<String>,
+    drain_timeout_secs: Option<u64>,
+    max_connections: Option<usize>,
+    max_connections_per_ip: Option<usize>,
+    max_requests_per_channel: Option<usize>,
+    rate_limit_burst: Option<u32>,
+    rate_limit_per_minute: Option<u32>,
+    generation_workers: Option<usize>,
+    puzzle_pool_depth: Option<usize>,
+    puzzle_pool_refill_per_minute: Option<u32>,
+    database: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileClientConfig {
+    connect: Option<SocketAddr>,
+    user: Option<String>,
+    password: Option<String>,
+    session: Option<String>,
+    request_timeout_secs: Option<u64>,
+    generate_timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub bind: IpAddr,
+    pub port: u16,
+    pub admin_token: Option<String>,
+    pub drain_timeout: Duration,
+    pub max_connections: usize,
+    pub max_connections_per_ip: usize,
+    pub max_requests_per_channel: usize,
+    pub rate_limit_burst: u32,
+    pub rate_limit_per_minute: u32,
+    pub generation_workers: usize,
+    pub puzzle_pool_depth: usize,
+    pub puzzle_pool_refill_per_minute: u32,
+    pub database: PathBuf,
+}
+
+impl ServerConfig {
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.bind, self.port)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// `None` means "connect to the server started by this same process".
+    pub connect: Option<SocketAddr>,
+    pub timeouts: Timeouts,
+    pub credentials: Credentials,
+}
+
+impl ClientConfig {
+    /// Address the client should use, given the address the local server actually bound to.
+    /// An unspecified bind address (`0.0.0.0` / `::`) is reached through loopback.
+    pub fn target(&self, local_server: SocketAddr) -> SocketAddr {
+        if let Some(addr) = self.connect {
+            return addr;
+        }
+
+        let ip = match local_server.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            ip => ip,
+        };
+        SocketAddr::new(ip, local_server.port())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub client: ClientConfig,
+}
+
+impl Config {
+    /// Builds the effective configuration: flags and environment first, then the config file,
+    /// then the defaults.
+    pub fn load(cli: &Cli) -> anyhow::Result<Self> {
+        let file = match &cli.config {
+            Some(path) => read_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => read_file(Path::new(DEFAULT_CONFIG_FILE))?,
+            None => FileConfig::default(),
+        };
+
+        Ok(Config {
+            server: ServerConfig {
+                bind: cli.bind.or(file.server.bind).unwrap_or(IpAddr::V6(Ipv6Addr::LOCALHOST)),
+                port: cli.port.or(file.server.port).unwrap_or(DEFAULT_PORT),
+                admin_token: cli.admin_token.clone().or(file.server.admin_token),
+                drain_timeout: Duration::from_secs(
+                    cli.drain_timeout.or(file.server.drain_timeout_secs).unwrap_or(DEFAULT_DRAIN_TIMEOUT_SECS),
+                ),
+                max_connections: cli.max_connections
+                    .or(file.server.max_connections)
+                    .unwrap_or(DEFAULT_MAX_CONNECTIONS),
+                max_connections_per_ip: cli.max_connections_per_ip
+                    .or(file.server.max_connections_per_ip)
+                    .unwrap_or(DEFAULT_MAX_CONNECTIONS_PER_IP),
+                max_requests_per_channel: cli.max_requests_per_channel
+                    .or(file.server.max_requests_per_channel)
+                    .unwrap_or(DEFAULT_MAX_REQUESTS_PER_CHANNEL),
+                rate_limit_burst: cli.rate_limit_burst
+                    .or(file.server.rate_limit_burst)
+                    .unwrap_or(DEFAULT_RATE_LIMIT_BURST),
+                rate_limit_per_minute: cli.rate_limit_per_minute
+                    .or(file.server.rate_limit_per_minute)
+                    .unwrap_or(DEFAULT_RATE_LIMIT_PER_MINUTE),
+                generation_workers: cli.generation_workers
+                    .or(file.server.generation_workers)
+                    .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get())),
+                puzzle_pool_depth: cli.puzzle_pool_depth
+                    .or(file.server.puzzle_pool_depth)
+                    .unwrap_or(DEFAULT_PUZZLE_POOL_DEPTH),
+                puzzle_pool_refill_per_minute: cli.puzzle_pool_refill_per_minute
+                    .or(file.server.puzzle_pool_refill_per_minute)
+                    .unwrap_or(DEFAULT_PUZZLE_POOL_REFILL_PER_MINUTE),
+                database: cli.database.clone()
+                    .or(file.server.database)
+                    .unwrap_or_else(|| PathBuf::from(DEFAULT_DATABASE)),
+            },
+            client: ClientConfig {
+                connect: cli.connect.or(file.client.connect),
+                credentials: Credentials {
+                    user: cli.user.clone().or(file.client.user),
+                    password: cli.password.clone().or(file.client.password),
+                    session_token: cli.session.clone().or(file.client.session),
+                },
+                timeouts: Timeouts {
+                    request: cli.request_timeout
+                        .or(file.client.request_timeout_secs)
+                        .map_or(Timeouts::default().request, Duration::from_secs),
+                    generate: cli.generate_timeout
+                        .or(file.client.generate_timeout_secs)
+                        .map_or(Timeouts::default().generate, Duration::from_secs),
+                },
+            },
+        })
+    }
+}
+
+fn read_file(path: &Path) -> anyhow::Result<FileConfig> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("no se pudo leer {}: {e}", path.display()))?;
+    toml::from_str(&text).map_err(|e| anyhow::anyhow!("configuración inválida en {}: {e}", path.display()))
+}
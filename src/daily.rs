@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use service::game::Streak;
+use service::sudoku::{Cancel, Difficulty, Sudoku, SudokuSize};
+use crate::storage::now_ms;
+use crate::workers::BlockingPool;
+
+const MS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
+/// Every daily puzzle is generated at this difficulty.
+pub const DAILY_DIFFICULTY: Difficulty = Difficulty::Medium;
+
+/// Current UTC day, counted from the Unix epoch.
+pub fn today() -> u64 {
+    now_ms() / MS_PER_DAY
+}
+
+fn seed(day: u64, size: SudokuSize) -> u64 {
+    day << 8 | size.box_size() as u64
+}
+
+/// Today's puzzle for each size. They are generated from a seed derived from the date, so every
+/// server (and a restarted one) hands out the same puzzles on the same day.
+#[derive(Default)]
+pub struct DailyPuzzles {
+    cache: Mutex<HashMap<SudokuSize, (u64, Sudoku)>>,
+}
+
+impl DailyPuzzles {
+    /// `day`'s puzzle of `size`, generated on `generators` the first time it is asked for.
+    pub async fn get(&self, day: u64, size: SudokuSize, generators: &BlockingPool, cancel: Cancel) -> Result<Sudoku, String> {
+        if let Some((cached_day, puzzle)) = self.cache.lock().unwrap().get(&size)
+            && *cached_day == day {
+            return Ok(puzzle.clone());
+        }
+
+        let puzzle = generators
+            .run(move || Sudoku::generate_seeded(size, DAILY_DIFFICULTY, seed(day, size), &cancel))
+            .await?;
+        self.cache.lock().unwrap().insert(size, (day, puzzle.clone()));
+        Ok(puzzle)
+    }
+}
+
+/// Current and best run of consecutive days in `days`, which must be sorted most recent first. A
+/// day repeated counts once.
+pub fn streak(days: &[u64], today: u64) -> Streak {
+    let mut best = 0;
+    let mut run = 0;
+    // longitud de la primera racha (la más reciente), fijada cuando se corta
+    let mut latest = None;
+
+    for (i, &day) in days.iter().enumerate() {
+        if i > 0 && days[i - 1] == day {
+            continue;
+        }
+        if i > 0 && days[i - 1] == day + 1 {
+            run += 1;
+        } else {
+            if i > 0 && latest.is_none() {
+                latest = Some(run);
+            }
+            run = 1;
+        }
+        best = best.max(run);
+    }
+
+    let alive = days.first().is_some_and(|&day| day + 1 >= today);
+    Streak {
+        current: if alive { latest.unwrap_or(run) } else { 0 },
+        best,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn streak_of(days: &[u64], today: u64) -> (u32, u32) {
+        let Streak { current, best } = streak(days, today);
+        (current, best)
+    }
+
+    #[test]
+    fn no_days_no_streak() {
+        assert_eq!(streak_of(&[], 100), (0, 0));
+    }
+
+    #[test]
+    fn run_ending_today() {
+        assert_eq!(streak_of(&[100, 99, 98], 100), (3, 3));
+    }
+
+    #[test]
+    fn today_not_solved_yet_keeps_yesterdays_run() {
+        assert_eq!(streak_of(&[99, 98], 100), (2, 2));
+    }
+
+    #[test]
+    fn run_broken_before_yesterday_is_over() {
+        assert_eq!(streak_of(&[98, 97, 96], 100), (0, 3));
+    }
+
+    #[test]
+    fn gap_splits_runs() {
+        assert_eq!(streak_of(&[100, 99, 95, 94, 93, 92], 100), (2, 4));
+        assert_eq!(streak_of(&[100, 98, 97], 100), (1, 2));
+    }
+
+    #[test]
+    fn repeated_days_count_once() {
+        assert_eq!(streak_of(&[100, 100, 99, 99, 98], 100), (3, 3));
+        assert_eq!(streak_of(&[100, 100], 100), (1, 1));
+    }
+
+    #[test]
+    fn seed_differs_between_sizes_on_the_same_day() {
+        let seeds: Vec<u64> = SudokuSize::ALL.iter().map(|&size| seed(100, size)).collect();
+        for (i, a) in seeds.iter().enumerate() {
+            assert!(seeds[i + 1..].iter().all(|b| a != b), "{seeds:?}");
+        }
+        assert_ne!(seed(100, SudokuSize::SUDOKU9X9), seed(101, SudokuSize::SUDOKU9X9));
+    }
+}
@@ -0,0 +1,47 @@
+use std::collections::VecDeque;
+use service::event::{Event, EventKind};
+use tokio::sync::watch;
+use crate::storage::now_ms;
+
+/// Events a game keeps for clients that fall behind; older ones are dropped.
+const MAX_EVENTS: usize = 256;
+
+/// Sequence numbers a log has room for per millisecond of its life before it could reach the
+/// first number of a log started later.
+const SEQ_PER_MS: u64 = 1000;
+
+/// The latest events of one game. They live in memory only, so a game gets a new log after a
+/// restart; its numbers start from the time it was created, above any number the earlier log gave
+/// out, and clients that kept theirs simply receive everything in the new one.
+pub struct EventLog {
+    recent: VecDeque<Event>,
+    /// `seq` of the last event, watched by `next_events`.
+    last: watch::Sender<u64>,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        EventLog { recent: VecDeque::new(), last: watch::Sender::new(now_ms() * SEQ_PER_MS) }
+    }
+}
+
+impl EventLog {
+    /// Appends an event and wakes everyone waiting for it.
+    pub fn push(&mut self, kind: EventKind) {
+        let seq = *self.last.borrow() + 1;
+        if self.recent.len() == MAX_EVENTS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(Event { seq, at_ms: now_ms(), kind });
+        self.last.send_replace(seq);
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.last.subscribe()
+    }
+
+    /// The events kept after `seq`.
+    pub fn since(&self, seq: u64) -> Vec<Event> {
+        self.recent.iter().filter(|event| event.seq > seq).cloned().collect()
+    }
+}
@@ -0,0 +1,765 @@
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use service::account::{Session, UserId};
+use service::event::{Event, EventKind};
+use service::game::{clear_peer_notes, Clock, Coop, Game, GameId, Move, SolveRecord, Streak};
+use service::replay::{Replay, ReplayMove};
+use service::sudoku::{Difficulty, Sudoku, SudokuSize, SudokuState};
+use service::ServiceError;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+use tokio::time;
+use crate::daily;
+use crate::events::EventLog;
+use crate::storage::{now_ms, storage_error, MoveKind, Storage, StoredMove};
+use crate::workers::BlockingPool;
+
+/// What a `place` did, beyond the resulting state.
+pub struct Placement {
+    pub state: SudokuState,
+    /// A `place` wrote a value that is not the one in the solution. Undo and redo never count.
+    pub mistake: bool,
+    /// Empty cells of the puzzle that now hold a value.
+    pub filled: usize,
+}
+
+/// Most entries a leaderboard request returns.
+const MAX_LEADERBOARD: u32 = 100;
+
+/// Players a cooperative game takes at most.
+const MAX_COOP_PLAYERS: usize = 8;
+
+/// Longest chat message, in characters.
+const MAX_CHAT_LEN: usize = 280;
+
+/// How long a game nobody asks for stays in memory.
+const IDLE_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// How long a finished game stays in memory, so those following it still get its last events.
+const FINISHED_TTL: Duration = Duration::from_secs(60);
+
+/// How often the games in memory are checked against `IDLE_TTL` and `FINISHED_TTL`.
+const SWEEP_EVERY: Duration = Duration::from_secs(60);
+
+/// How long before the request deadline a long-poll answers anyway, so the reply reaches the
+/// client in time.
+pub const LONG_POLL_MARGIN: Duration = Duration::from_secs(1);
+
+/// Who plays a cooperative game and who filled each cell.
+struct CoopBoard {
+    players: Vec<(UserId, String)>,
+    /// See [`Coop::filled_by`].
+    filled_by: Vec<Vec<u8>>,
+}
+
+impl CoopBoard {
+    /// Rebuilds who filled each cell of an `n`x`n` board from the game's `moves`.
+    fn new(players: Vec<(UserId, String)>, n: usize, moves: &[StoredMove]) -> Self {
+        let mut coop = CoopBoard { players, filled_by: vec![vec![0; n]; n] };
+        for StoredMove { mv, user, .. } in moves {
+            let author = user.as_ref().and_then(|&(user, _)| coop.index(user)).map_or(0, |i| i as u8 + 1);
+            coop.filled_by[mv.row as usize][mv.col as usize] = if mv.value == 0 { 0 } else { author };
+        }
+        coop
+    }
+
+    fn index(&self, user: UserId) -> Option<usize> {
+        self.players.iter().position(|&(id, _)| id == user)
+    }
+}
+
+/// One `place`, as undo and redo see it.
+#[derive(Clone, Copy)]
+struct Edit {
+    row: u8,
+    col: u8,
+    before: u8,
+    after: u8,
+}
+
+/// What one player can undo and redo in a game.
+#[derive(Default)]
+struct History {
+    done: Vec<Edit>,
+    undone: Vec<Edit>,
+}
+
+impl History {
+    /// Every player's history, rebuilt from the game's `moves` starting from `puzzle`.
+    fn rebuild(puzzle: &[Vec<u8>], moves: &[StoredMove]) -> HashMap<Option<UserId>, History> {
+        let mut board = puzzle.to_vec();
+        let mut histories: HashMap<Option<UserId>, History> = HashMap::new();
+        for StoredMove { mv, user, kind } in moves {
+            let cell = &mut board[mv.row as usize][mv.col as usize];
+            let edit = Edit { row: mv.row, col: mv.col, before: *cell, after: mv.value };
+            histories.entry(user.as_ref().map(|&(id, _)| id)).or_default().record(*kind, edit);
+            *cell = mv.value;
+        }
+        histories
+    }
+
+    fn record(&mut self, kind: MoveKind, edit: Edit) {
+        match kind {
+            MoveKind::Place => {
+                self.done.push(edit);
+                self.undone.clear();
+            }
+            MoveKind::Undo => self.undone.extend(self.done.pop()),
+            MoveKind::Redo => self.done.extend(self.undone.pop()),
+        }
+    }
+}
+
+/// When a game started and ended, and how long it has been paused, in milliseconds since the
+/// Unix epoch.
+struct Timer {
+    started_at: u64,
+    finished_at: Option<u64>,
+    /// Time spent in pauses that are over.
+    paused_ms: u64,
+    /// When the current pause began.
+    paused_at: Option<u64>,
+}
+
+impl Timer {
+    fn new(started_at: u64) -> Self {
+        Timer { started_at, finished_at: None, paused_ms: 0, paused_at: None }
+    }
+
+    /// The clock at `now`; it stops when the game is paused or finished.
+    fn clock(&self, now: u64) -> Clock {
+        let until = self.finished_at.or(self.paused_at).unwrap_or(now);
+        Clock {
+            elapsed_ms: until.saturating_sub(self.started_at).saturating_sub(self.paused_ms),
+            paused: self.paused_at.is_some(),
+        }
+    }
+}
+
+/// A game being played, solution included.
+struct ActiveGame {
+    puzzle: Sudoku,
+    board: Vec<Vec<u8>>,
+    /// Only this player may play the game; if `None`, whoever has its id, which cannot be guessed.
+    /// Spectators see it either way.
+    user: Option<UserId>,
+    daily: Option<u64>,
+    timer: Timer,
+    /// See [`Game::mistakes`].
+    mistakes: u32,
+    /// See [`Game::notes`].
+    notes: Vec<Vec<u16>>,
+    coop: Option<CoopBoard>,
+    events: EventLog,
+    /// Undo and redo stacks of each player.
+    history: HashMap<Option<UserId>, History>,
+}
+
+impl ActiveGame {
+    fn state(&self) -> SudokuState {
+        self.puzzle.check_user_board(&self.board, self.puzzle.sudoku_size)
+    }
+
+    /// What players get to see: the current board, without the solution.
+    fn view(&self, id: GameId) -> Game {
+        Game {
+            id,
+            sudoku: Sudoku {
+                board: self.board.clone(),
+                solved: Vec::new(),
+                sudoku_size: self.puzzle.sudoku_size,
+                difficulty: self.puzzle.difficulty,
+                state: self.state(),
+            },
+            puzzle: self.puzzle.board.clone(),
+            daily: self.daily,
+            coop: self.coop.as_ref().map(|coop| Coop {
+                players: coop.players.iter().map(|(_, name)| name.clone()).collect(),
+                filled_by: coop.filled_by.clone(),
+            }),
+            clock: self.timer.clock(now_ms()),
+            mistakes: self.mistakes,
+            notes: self.notes.clone(),
+        }
+    }
+
+    fn may_play(&self, user: Option<UserId>) -> bool {
+        match (&self.coop, self.user) {
+            (Some(coop), _) => user.is_some_and(|user| coop.index(user).is_some()),
+            (None, Some(owner)) => user == Some(owner),
+            (None, None) => true,
+        }
+    }
+}
+
+/// A game taken by one request: locked in the cache, or read from storage only to be looked at.
+enum Held {
+    Cached(OwnedMutexGuard<ActiveGame>),
+    /// A finished game that was not in memory. It is not cached, so it must not be changed.
+    Detached(Box<ActiveGame>),
+}
+
+impl Deref for Held {
+    type Target = ActiveGame;
+
+    fn deref(&self) -> &ActiveGame {
+        match self {
+            Held::Cached(game) => game,
+            Held::Detached(game) => game,
+        }
+    }
+}
+
+impl DerefMut for Held {
+    fn deref_mut(&mut self) -> &mut ActiveGame {
+        match self {
+            Held::Cached(game) => game,
+            Held::Detached(game) => game,
+        }
+    }
+}
+
+/// A game kept in memory, behind a lock of its own.
+struct Cached {
+    game: Arc<AsyncMutex<ActiveGame>>,
+    /// Last time a request took it.
+    used: Instant,
+    /// When it was seen finished, if it is.
+    finished: Option<Instant>,
+}
+
+struct Cache {
+    games: HashMap<GameId, Cached>,
+    swept: Instant,
+}
+
+impl Cache {
+    /// Drops the games nobody holds that are finished since `FINISHED_TTL` or idle since
+    /// `IDLE_TTL`. They are read from storage again if asked for.
+    fn sweep(&mut self) {
+        self.swept = Instant::now();
+        self.games.retain(|id, cached| {
+            let expired = cached.finished.is_some_and(|t| t.elapsed() > FINISHED_TTL) || cached.used.elapsed() > IDLE_TTL;
+            let evict = expired && Arc::strong_count(&cached.game) == 1;
+            if evict {
+                tracing::debug!(target: "server", "Partida {id} fuera de memoria");
+            }
+            !evict
+        });
+    }
+}
+
+/// Games in progress. Each one has its own lock, so moves on different games never wait for each
+/// other, and the map of games is never locked while storage is used. Every change is written
+/// through to `storage`, on the blocking threads of `io`, before it is acknowledged, so a
+/// restarted server picks games up where they were left.
+pub struct Games {
+    storage: Arc<dyn Storage>,
+    io: BlockingPool,
+    cache: Mutex<Cache>,
+}
+
+impl Games {
+    pub fn new(storage: Arc<dyn Storage>, io: BlockingPool) -> Self {
+        Games { storage, io, cache: Mutex::new(Cache { games: HashMap::new(), swept: Instant::now() }) }
+    }
+
+    /// Stores `puzzle` and starts a game on it, owned by `user` if the player is logged in.
+    pub async fn start(&self, puzzle: Sudoku, user: Option<UserId>) -> Result<Game, ServiceError> {
+        self.start_on(puzzle, user, None).await
+    }
+
+    /// Starts `day`'s daily `puzzle`, or returns the game `user` already started on it.
+    pub async fn start_daily(&self, puzzle: Sudoku, user: Option<UserId>, day: u64) -> Result<Game, ServiceError> {
+        if let Some(user) = user {
+            let size = puzzle.sudoku_size;
+            if let Some(id) = self.stored(move |storage| storage.daily_game(user, day, size)).await? {
+                return self.resume(id, Some(user)).await;
+            }
+        }
+        self.start_on(puzzle, user, Some(day)).await
+    }
+
+    async fn start_on(&self, puzzle: Sudoku, user: Option<UserId>, daily: Option<u64>) -> Result<Game, ServiceError> {
+        let now = now_ms();
+        let (id, puzzle) = self.stored(move |storage| {
+            let puzzle_id = storage.save_puzzle(&puzzle)?;
+            let id = storage.create_game(puzzle_id, user, daily, &puzzle.board, now)?;
+            Ok((id, puzzle))
+        }).await?;
+
+        let n = puzzle.board.len();
+        let game = ActiveGame { board: puzzle.board.clone(), puzzle, user, daily,
+            timer: Timer::new(now),
+            mistakes: 0,
+            notes: vec![vec![0; n]; n],
+            coop: None,
+            events: EventLog::default(),
+            history: HashMap::new(),
+        };
+        Ok(self.insert(id, game))
+    }
+
+    /// Starts a cooperative game on `puzzle` with `host` as its first player. Nobody owns it; its
+    /// players may all play it.
+    pub async fn start_coop(&self, puzzle: Sudoku, host: &Session) -> Result<Game, ServiceError> {
+        let now = now_ms();
+        let host_id = host.user_id;
+        let (id, puzzle) = self.stored(move |storage| {
+            let puzzle_id = storage.save_puzzle(&puzzle)?;
+            let id = storage.create_game(puzzle_id, None, None, &puzzle.board, now)?;
+            storage.add_player(id, host_id, now)?;
+            Ok((id, puzzle))
+        }).await?;
+
+        let n = puzzle.board.len();
+        let coop = CoopBoard::new(vec![(host.user_id, host.username.clone())], n, &[]);
+        let game = ActiveGame {
+            board: puzzle.board.clone(),
+            puzzle,
+            user: None,
+            daily: None,
+            timer: Timer::new(now),
+            mistakes: 0,
+            notes: vec![vec![0; n]; n],
+            coop: Some(coop),
+            events: EventLog::default(),
+            history: HashMap::new(),
+        };
+        Ok(self.insert(id, game))
+    }
+
+    /// Adds `session`'s player to the cooperative game `id`, unless they already play it.
+    pub async fn join_coop(&self, id: GameId, session: &Session) -> Result<Game, ServiceError> {
+        let mut game = self.open(id, true).await?;
+        let Some(coop) = &game.coop else {
+            return Err(ServiceError::InvalidMove(format!("la partida {id} no es cooperativa")));
+        };
+
+        if coop.index(session.user_id).is_none() {
+            if game.timer.finished_at.is_some() {
+                return Err(ServiceError::InvalidMove("la partida ya terminó".into()));
+            }
+            if coop.players.len() >= MAX_COOP_PLAYERS {
+                return Err(ServiceError::InvalidMove(format!("la partida está completa (máximo {MAX_COOP_PLAYERS})")));
+            }
+            let user = session.user_id;
+            self.stored(move |storage| storage.add_player(id, user, now_ms())).await?;
+            if let Some(coop) = &mut game.coop {
+                coop.players.push((session.user_id, session.username.clone()));
+            }
+            game.events.push(EventKind::Joined { player: session.username.clone() });
+            tracing::info!(target: "server", "{} se une a la partida {id}", session.username);
+        }
+        Ok(game.view(id))
+    }
+
+    /// Game `id` as its players see it, for anyone who asks.
+    pub async fn spectate(&self, id: GameId) -> Result<Game, ServiceError> {
+        Ok(self.open(id, false).await?.view(id))
+    }
+
+    /// Game `id` from its puzzle to its last move, for anyone who asks. Read from storage only, so
+    /// it never brings the game into memory.
+    pub async fn replay(&self, id: GameId) -> Result<Replay, ServiceError> {
+        let (stored, moves) = self.stored(move |storage| Ok((storage.load_game(id)?, storage.moves(id)?))).await?;
+        let stored = stored.ok_or(ServiceError::GameNotFound(id))?;
+
+        Ok(Replay {
+            game_id: id,
+            size: stored.puzzle.sudoku_size,
+            difficulty: stored.puzzle.difficulty,
+            puzzle: stored.puzzle.board,
+            started_at: stored.started_at,
+            finished_at: stored.finished_at,
+            moves: moves.into_iter()
+                .map(|StoredMove { mv, user, .. }| ReplayMove { mv, player: user.map(|(_, name)| name) })
+                .collect(),
+        })
+    }
+
+    /// The events of game `id` after `since`, waiting for the next one if there are none, but
+    /// answering anyway when `deadline` is close. Spectators may read them too.
+    pub async fn next_events(
+        &self,
+        id: GameId,
+        user: Option<UserId>,
+        spectator: bool,
+        since: u64,
+        deadline: Instant,
+    ) -> Result<Vec<Event>, ServiceError> {
+        let last = match &self.readable(id, user, spectator).await? {
+            Held::Cached(game) => Some(game.events.subscribe()),
+            Held::Detached(_) => None,
+        };
+
+        let until = deadline.checked_sub(LONG_POLL_MARGIN).unwrap_or(deadline);
+        match last {
+            Some(mut last) => {
+                let _ = time::timeout_at(until.into(), last.wait_for(|&seq| seq > since)).await;
+            }
+            // una partida terminada que ya no está en memoria no tendrá más eventos
+            None => time::sleep_until(until.into()).await,
+        }
+
+        Ok(self.readable(id, user, spectator).await?.events.since(since))
+    }
+
+    /// Sends `text` from `session`'s player to everyone following game `id`.
+    pub async fn chat(&self, id: GameId, session: &Session, text: String) -> Result<(), ServiceError> {
+        let text = text.trim();
+        if text.is_empty() || text.chars().count() > MAX_CHAT_LEN {
+            return Err(ServiceError::InvalidMessage(format!("debe tener entre 1 y {MAX_CHAT_LEN} caracteres")));
+        }
+
+        let mut game = self.load(id, Some(session.user_id)).await?;
+        game.events.push(EventKind::Chat { player: session.username.clone(), text: text.to_string() });
+        Ok(())
+    }
+
+    pub async fn resume(&self, id: GameId, user: Option<UserId>) -> Result<Game, ServiceError> {
+        Ok(self.load(id, user).await?.view(id))
+    }
+
+    /// Writes `value` at `row`, `col`. Given cells cannot be changed and finished games take no
+    /// more moves. In a cooperative game a filled cell belongs to whoever filled it until they
+    /// clear it: moves are applied in the order the server gets them, and a later write to that
+    /// cell by someone else is rejected.
+    pub async fn place(&self, id: GameId, player: Option<&Session>, row: u8, col: u8, value: u8) -> Result<(Placement, Game), ServiceError> {
+        let mut game = self.load(id, player.map(|player| player.user_id)).await?;
+        let placement = self.write(id, &mut game, player, (row, col), value, MoveKind::Place).await?;
+        Ok((placement, game.view(id)))
+    }
+
+    /// Adds `value` to the notes of the empty cell at `row`, `col`, or takes it out if it is
+    /// there; 0 clears the cell's notes. Notes are kept apart from moves: they are not in the
+    /// history or replays, and followers get them as an [`EventKind::Note`].
+    pub async fn note(&self, id: GameId, player: Option<&Session>, row: u8, col: u8, value: u8) -> Result<Game, ServiceError> {
+        let mut game = self.load(id, player.map(|player| player.user_id)).await?;
+        let n = game.board.len();
+        let (r, c) = (row as usize, col as usize);
+        if game.timer.finished_at.is_some() {
+            return Err(ServiceError::InvalidMove("la partida ya terminó".into()));
+        }
+        if game.timer.paused_at.is_some() {
+            return Err(ServiceError::InvalidMove("la partida está en pausa".into()));
+        }
+        if r >= n || c >= n || value as usize > n {
+            return Err(ServiceError::InvalidMove("fuera del tablero".into()));
+        }
+        if game.board[r][c] != 0 {
+            return Err(ServiceError::InvalidMove("la celda ya tiene un valor".into()));
+        }
+
+        let marks = match value {
+            0 => 0,
+            value => game.notes[r][c] ^ (1 << (value - 1)),
+        };
+        self.stored(move |storage| storage.save_notes(id, &[(row, col, marks)])).await?;
+        game.notes[r][c] = marks;
+        let player = player.map(|player| player.username.clone());
+        game.events.push(EventKind::Note { player, row, col, marks });
+        Ok(game.view(id))
+    }
+
+    /// Stops or restarts the clock of game `id`. Only games with a single player can be paused,
+    /// and finished games have nothing left to time.
+    pub async fn pause(&self, id: GameId, user: Option<UserId>, paused: bool) -> Result<Game, ServiceError> {
+        let mut game = self.load(id, user).await?;
+        if game.coop.is_some() {
+            return Err(ServiceError::InvalidMove("las partidas cooperativas no se pueden pausar".into()));
+        }
+        if game.timer.finished_at.is_some() {
+            return Err(ServiceError::InvalidMove("la partida ya terminó".into()));
+        }
+
+        let now = now_ms();
+        let timer = &game.timer;
+        let (paused_at, paused_ms) = match (paused, timer.paused_at) {
+            (true, None) => (Some(now), timer.paused_ms),
+            (false, Some(since)) => (None, timer.paused_ms + now.saturating_sub(since)),
+            _ => return Ok(game.view(id)),
+        };
+        self.stored(move |storage| storage.set_paused(id, paused_at, paused_ms)).await?;
+        game.timer.paused_at = paused_at;
+        game.timer.paused_ms = paused_ms;
+        tracing::info!(target: "server", "Partida {id} {}", if paused { "en pausa" } else { "reanudada" });
+        Ok(game.view(id))
+    }
+
+    /// Takes back `player`'s last move, or replays the last one taken back if `kind` is
+    /// [`MoveKind::Redo`]. Each is a move of its own, checked like any other.
+    pub async fn undo(&self, id: GameId, player: Option<&Session>, kind: MoveKind) -> Result<(Placement, Game), ServiceError> {
+        let user = player.map(|player| player.user_id);
+        let mut game = self.load(id, user).await?;
+
+        let history = game.history.get(&user);
+        let (edit, value) = match kind {
+            MoveKind::Undo => history.and_then(|h| h.done.last()).map(|edit| (*edit, edit.before)),
+            _ => history.and_then(|h| h.undone.last()).map(|edit| (*edit, edit.after)),
+        }.ok_or_else(|| ServiceError::InvalidMove(match kind {
+            MoveKind::Undo => "no hay nada que deshacer".to_string(),
+            _ => "no hay nada que rehacer".to_string(),
+        }))?;
+
+        let placement = self.write(id, &mut game, player, (edit.row, edit.col), value, kind).await?;
+        Ok((placement, game.view(id)))
+    }
+
+    async fn write(
+        &self,
+        id: GameId,
+        game: &mut ActiveGame,
+        player: Option<&Session>,
+        (row, col): (u8, u8),
+        value: u8,
+        kind: MoveKind,
+    ) -> Result<Placement, ServiceError> {
+        let user = player.map(|player| player.user_id);
+        let n = game.board.len();
+        let (r, c) = (row as usize, col as usize);
+        if game.timer.finished_at.is_some() {
+            return Err(ServiceError::InvalidMove("la partida ya terminó".into()));
+        }
+        if game.timer.paused_at.is_some() {
+            return Err(ServiceError::InvalidMove("la partida está en pausa".into()));
+        }
+        if r >= n || c >= n || value as usize > n {
+            return Err(ServiceError::InvalidMove("fuera del tablero".into()));
+        }
+        if game.puzzle.board[r][c] != 0 {
+            return Err(ServiceError::InvalidMove("la celda es una pista".into()));
+        }
+        let author = match &game.coop {
+            Some(coop) => {
+                let me = user.and_then(|user| coop.index(user)).map_or(0, |i| i as u8 + 1);
+                let filled_by = coop.filled_by[r][c];
+                if filled_by != 0 && filled_by != me {
+                    let name = &coop.players[filled_by as usize - 1].1;
+                    return Err(ServiceError::InvalidMove(format!("la celda ya la rellenó {name}")));
+                }
+                me
+            }
+            None => 0,
+        };
+
+        let mut board = game.board.clone();
+        board[r][c] = value;
+        let mut notes = game.notes.clone();
+        let cleared: Vec<_> = clear_peer_notes(&mut notes, r, c, value)
+            .into_iter()
+            .map(|(r, c)| (r as u8, c as u8, notes[r][c]))
+            .collect();
+        let mv = Move { row, col, value, at_ms: now_ms() };
+        let at_ms = mv.at_ms;
+        let board = self.stored(move |storage| {
+            storage.record_move(id, &mv, user, kind, &board)?;
+            if !cleared.is_empty() {
+                storage.save_notes(id, &cleared)?;
+            }
+            Ok(board)
+        }).await?;
+        let edit = Edit { row, col, before: game.board[r][c], after: value };
+        game.history.entry(user).or_default().record(kind, edit);
+        game.board = board;
+        game.notes = notes;
+
+        if let Some(coop) = &mut game.coop {
+            coop.filled_by[r][c] = if value == 0 { 0 } else { author };
+        }
+        let player = player.map(|player| player.username.clone());
+        game.events.push(EventKind::Move { player: player.clone(), row, col, value });
+
+        let state = game.state();
+        if state == SudokuState::Solved {
+            self.stored(move |storage| storage.finish_game(id, at_ms)).await?;
+            game.timer.finished_at = Some(at_ms);
+            if let Some(cached) = self.cache.lock().unwrap().games.get_mut(&id) {
+                cached.finished = Some(Instant::now());
+            }
+            game.events.push(EventKind::Completed { player });
+            tracing::info!(target: "server", "Partida {id} resuelta");
+        }
+
+        let filled = game.puzzle.board.iter().flatten()
+            .zip(game.board.iter().flatten())
+            .filter(|&(&given, &cell)| given == 0 && cell != 0)
+            .count();
+        let mistake = kind == MoveKind::Place && value != 0 && value != game.puzzle.solved[r][c];
+        game.mistakes += mistake as u32;
+        Ok(Placement { state, mistake, filled })
+    }
+
+    pub async fn leaderboard(&self, size: SudokuSize, difficulty: Difficulty, limit: u32) -> Result<Vec<SolveRecord>, ServiceError> {
+        self.stored(move |storage| storage.leaderboard(size, difficulty, limit.min(MAX_LEADERBOARD))).await
+    }
+
+    pub async fn personal_best(&self, user: UserId, size: SudokuSize, difficulty: Difficulty) -> Result<Option<SolveRecord>, ServiceError> {
+        self.stored(move |storage| storage.personal_best(user, size, difficulty)).await
+    }
+
+    pub async fn daily_leaderboard(&self, day: u64, size: SudokuSize, limit: u32) -> Result<Vec<SolveRecord>, ServiceError> {
+        self.stored(move |storage| storage.daily_leaderboard(day, size, limit.min(MAX_LEADERBOARD))).await
+    }
+
+    pub async fn streak(&self, user: UserId, today: u64) -> Result<Streak, ServiceError> {
+        let days = self.stored(move |storage| storage.daily_days_solved(user)).await?;
+        Ok(daily::streak(&days, today))
+    }
+
+    /// Makes everything written so far durable; called once the server stops serving.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        self.storage.flush()
+    }
+
+    /// Runs `job` against the storage on one of the `io` threads.
+    async fn stored<T, F>(&self, job: F) -> Result<T, ServiceError>
+    where
+        F: FnOnce(&dyn Storage) -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let storage = self.storage.clone();
+        self.io.run(move || job(storage.as_ref())).await.map_err(storage_error)
+    }
+
+    /// Keeps a game just started in memory and returns what its players see.
+    fn insert(&self, id: GameId, game: ActiveGame) -> Game {
+        let view = game.view(id);
+        let cached = Cached { game: Arc::new(AsyncMutex::new(game)), used: Instant::now(), finished: None };
+        self.cache.lock().unwrap().games.insert(id, cached);
+        view
+    }
+
+    /// The game `id`, as long as `user` may play it.
+    async fn load(&self, id: GameId, user: Option<UserId>) -> Result<Held, ServiceError> {
+        let game = self.open(id, true).await?;
+        if game.may_play(user) { Ok(game) } else { Err(ServiceError::NotYourGame(id)) }
+    }
+
+    /// The game `id`, for its players or, if `spectator`, anyone. Only read, so a finished game
+    /// not in memory stays out of it.
+    async fn readable(&self, id: GameId, user: Option<UserId>, spectator: bool) -> Result<Held, ServiceError> {
+        let game = self.open(id, false).await?;
+        if spectator || game.may_play(user) { Ok(game) } else { Err(ServiceError::NotYourGame(id)) }
+    }
+
+    /// The game `id`, whoever plays it, locked until the returned value is dropped. If it is not
+    /// in memory it is read from storage and kept, unless it is finished and not `keep_finished`.
+    async fn open(&self, id: GameId, keep_finished: bool) -> Result<Held, ServiceError> {
+        let cached = {
+            let mut cache = self.cache.lock().unwrap();
+            if cache.swept.elapsed() > SWEEP_EVERY {
+                cache.sweep();
+            }
+            cache.games.get_mut(&id).map(|cached| {
+                cached.used = Instant::now();
+                cached.game.clone()
+            })
+        };
+        if let Some(game) = cached {
+            return Ok(Held::Cached(game.lock_owned().await));
+        }
+
+        let game = self.read(id).await?;
+        let finished = game.timer.finished_at.map(|_| Instant::now());
+        if finished.is_some() && !keep_finished {
+            return Ok(Held::Detached(Box::new(game)));
+        }
+        // si otra petición la cargó mientras tanto, vale la suya
+        let game = self.cache.lock().unwrap().games
+            .entry(id)
+            .or_insert_with(|| Cached { game: Arc::new(AsyncMutex::new(game)), used: Instant::now(), finished })
+            .game
+            .clone();
+        Ok(Held::Cached(game.lock_owned().await))
+    }
+
+    /// The game `id` as storage has it.
+    async fn read(&self, id: GameId) -> Result<ActiveGame, ServiceError> {
+        let (stored, moves) = self.stored(move |storage| Ok((storage.load_game(id)?, storage.moves(id)?))).await?;
+        let stored = stored.ok_or(ServiceError::GameNotFound(id))?;
+        let history = History::rebuild(&stored.puzzle.board, &moves);
+        let mistakes = moves.iter()
+            .filter(|StoredMove { mv, kind, .. }| {
+                *kind == MoveKind::Place && mv.value != 0 && mv.value != stored.puzzle.solved[mv.row as usize][mv.col as usize]
+            })
+            .count() as u32;
+        let coop = match stored.players.is_empty() {
+            true => None,
+            false => Some(CoopBoard::new(stored.players, stored.board.len(), &moves)),
+        };
+        Ok(ActiveGame {
+            puzzle: stored.puzzle,
+            board: stored.board,
+            user: stored.user,
+            daily: stored.daily,
+            timer: Timer {
+                started_at: stored.started_at,
+                finished_at: stored.finished_at,
+                paused_ms: stored.paused_ms,
+                paused_at: stored.paused_at,
+            },
+            mistakes,
+            notes: stored.notes,
+            coop,
+            events: EventLog::default(),
+            history,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(user: UserId, kind: MoveKind, row: u8, col: u8, value: u8) -> StoredMove {
+        let name = if user == 1 { "ana" } else { "bob" };
+        StoredMove { mv: Move { row, col, value, at_ms: 0 }, user: Some((user, name.to_string())), kind }
+    }
+
+    fn edits(edits: &[Edit]) -> Vec<(u8, u8, u8, u8)> {
+        edits.iter().map(|edit| (edit.row, edit.col, edit.before, edit.after)).collect()
+    }
+
+    #[test]
+    fn history_rebuilt_per_player_in_a_coop_game() {
+        let puzzle = vec![vec![1, 0, 0, 4], vec![0; 4], vec![0; 4], vec![4, 0, 0, 1]];
+        let moves = [
+            stored(1, MoveKind::Place, 0, 1, 2),
+            stored(2, MoveKind::Place, 0, 2, 3),
+            stored(1, MoveKind::Place, 1, 0, 2),
+            // ana deshace sus dos jugadas entre las de bob
+            stored(1, MoveKind::Undo, 1, 0, 0),
+            stored(2, MoveKind::Place, 1, 1, 4),
+            stored(1, MoveKind::Undo, 0, 1, 0),
+            stored(2, MoveKind::Undo, 1, 1, 0),
+            stored(1, MoveKind::Redo, 0, 1, 2),
+            stored(2, MoveKind::Place, 2, 2, 4),
+        ];
+        let histories = History::rebuild(&puzzle, &moves);
+
+        let ana = &histories[&Some(1)];
+        assert_eq!(edits(&ana.done), [(0, 1, 0, 2)]);
+        assert_eq!(edits(&ana.undone), [(1, 0, 0, 2)]);
+
+        // una jugada nueva de bob vacía lo que tenía por rehacer
+        let bob = &histories[&Some(2)];
+        assert_eq!(edits(&bob.done), [(0, 2, 0, 3), (2, 2, 0, 4)]);
+        assert!(bob.undone.is_empty());
+        assert_eq!(histories.len(), 2);
+    }
+
+    #[test]
+    fn history_of_a_cell_another_player_changed_keeps_the_value_they_saw() {
+        let puzzle = vec![vec![0; 4]; 4];
+        let moves = [
+            stored(1, MoveKind::Place, 0, 0, 1),
+            stored(2, MoveKind::Place, 0, 0, 0),
+            stored(2, MoveKind::Place, 0, 0, 3),
+        ];
+        let histories = History::rebuild(&puzzle, &moves);
+        assert_eq!(edits(&histories[&Some(1)].done), [(0, 0, 0, 1)]);
+        assert_eq!(edits(&histories[&Some(2)].done), [(0, 0, 1, 0), (0, 0, 0, 3)]);
+    }
+}
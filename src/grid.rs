@@ -0,0 +1,269 @@
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Widget},
+};
+
+/// Cell sizes tried from largest to smallest, as (width, height) in terminal cells; the grid uses
+/// the largest that fits.
+const CELL_SIZES: [(u16, u16); 5] = [(7, 3), (5, 2), (3, 1), (2, 1), (1, 1)];
+
+/// A sudoku board drawn with lines between its boxes. Givens are bold, the player's values are
+/// colored and values repeated in a row, column or box are red. Empty cells show their notes as a
+/// small grid when the cells are big enough, or else one after another filling the cell, ending in
+/// a `+` when they do not all fit.
+pub struct Grid<'a> {
+    board: &'a [Vec<u8>],
+    givens: &'a [Vec<u8>],
+    /// Who wrote each cell (0 for nobody, `i + 1` for `colors[i]`) in cooperative games.
+    authors: Option<(&'a [Vec<u8>], &'a [Color])>,
+    /// Candidates noted in each cell, bit `v - 1` for `v`.
+    notes: Option<&'a [Vec<u16>]>,
+    cursor: Option<(usize, usize)>,
+    footer: Vec<Line<'a>>,
+    block: Option<Block<'a>>,
+}
+
+impl<'a> Grid<'a> {
+    /// `givens` is the board as generated; its non-zero cells are the puzzle's.
+    pub fn new(board: &'a [Vec<u8>], givens: &'a [Vec<u8>]) -> Self {
+        Grid { board, givens, authors: None, notes: None, cursor: None, footer: Vec::new(), block: None }
+    }
+
+    /// Colors each value by its author, `colors[author - 1]`, instead of the player color.
+    pub fn authors(mut self, filled_by: &'a [Vec<u8>], colors: &'a [Color]) -> Self {
+        self.authors = Some((filled_by, colors));
+        self
+    }
+
+    /// Shows the candidates noted in each empty cell, bit `v - 1` of `notes[row][col]` for `v`.
+    pub fn notes(mut self, notes: &'a [Vec<u16>]) -> Self {
+        self.notes = Some(notes);
+        self
+    }
+
+    /// Highlights the cell at `(row, col)` and its row, column and box.
+    pub fn cursor(mut self, cursor: Option<(usize, usize)>) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    /// Lines shown centered under the board.
+    pub fn footer(mut self, footer: Vec<Line<'a>>) -> Self {
+        self.footer = footer;
+        self
+    }
+
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// The cell drawn at column `x`, row `y` when the grid is rendered in `area`, if any.
+    pub fn cell_at(&self, area: Rect, x: u16, y: u16) -> Option<(usize, usize)> {
+        let area = self.block.as_ref().map_or(area, |block| block.inner(area));
+        let geometry = self.geometry(area)?;
+        let find = |position: u16, start: u16, size: u16| {
+            (0..self.board.len()).find(|&i| {
+                let from = start + geometry.offset(i, size);
+                (from..from + size).contains(&position)
+            })
+        };
+        Some((find(y, geometry.y, geometry.cell_height)?, find(x, geometry.x, geometry.cell_width)?))
+    }
+
+    /// Where the board goes inside `area`: the largest cell size that fits above the footer,
+    /// centered.
+    fn geometry(&self, area: Rect) -> Option<Geometry> {
+        let n = self.board.len() as u16;
+        if n == 0 || area.is_empty() {
+            return None;
+        }
+        let box_size = self.box_size();
+        let footer_height = match self.footer.len() as u16 {
+            0 => 0,
+            lines => lines + 1,
+        };
+        let separators = box_size as u16 - 1;
+        let room = Rect { height: area.height.saturating_sub(footer_height), ..area };
+        let (cell_width, cell_height) = CELL_SIZES
+            .into_iter()
+            .find(|&(w, h)| w * n + separators <= room.width && h * n + separators <= room.height)
+            .unwrap_or(CELL_SIZES[CELL_SIZES.len() - 1]);
+        let width = cell_width * n + separators;
+        let height = cell_height * n + separators;
+        Some(Geometry {
+            x: room.x + room.width.saturating_sub(width) / 2,
+            y: room.y + room.height.saturating_sub(height) / 2,
+            width,
+            height,
+            cell_width,
+            cell_height,
+            box_size,
+        })
+    }
+
+    fn box_size(&self) -> usize {
+        (self.board.len() as f64).sqrt() as usize
+    }
+
+    /// Whether `(r, c)` is in the same row, column or box as the cursor.
+    fn is_peer(&self, r: usize, c: usize) -> bool {
+        let b = self.box_size();
+        self.cursor.is_some_and(|(cr, cc)| cr == r || cc == c || (cr / b == r / b && cc / b == c / b))
+    }
+
+    /// Whether the value at `(r, c)` appears again in its row, column or box.
+    fn conflicts(&self, r: usize, c: usize) -> bool {
+        let value = self.board[r][c];
+        let b = self.box_size();
+        value != 0 && self.board.iter().enumerate().any(|(r2, row)| {
+            row.iter().enumerate().any(|(c2, &other)| {
+                other == value
+                    && (r2, c2) != (r, c)
+                    && (r2 == r || c2 == c || (r2 / b == r / b && c2 / b == c / b))
+            })
+        })
+    }
+
+    fn cell_style(&self, r: usize, c: usize) -> Style {
+        let mut style = if self.givens.get(r).and_then(|row| row.get(c)).is_some_and(|&v| v != 0) {
+            Style::default().add_modifier(Modifier::BOLD)
+        } else {
+            match self.authors.map(|(filled_by, colors)| (filled_by[r][c], colors)) {
+                Some((author, colors)) if author > 0 => Style::default().fg(colors[(author as usize - 1) % colors.len()]),
+                _ => Style::default().fg(Color::Cyan),
+            }
+        };
+        if self.conflicts(r, c) {
+            style = style.fg(Color::Red);
+        }
+        if self.cursor == Some((r, c)) {
+            style = style.bg(Color::Yellow).fg(Color::Black);
+        } else if self.is_peer(r, c) {
+            style = style.bg(Color::DarkGray);
+        }
+        style
+    }
+}
+
+impl Widget for Grid<'_> {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let area = match self.block.take() {
+            Some(block) => {
+                let inner = block.inner(area);
+                block.render(area, buf);
+                inner
+            }
+            None => area,
+        };
+        let Some(geometry) = self.geometry(area) else { return };
+        let Geometry { x: left, y: top, width, height, cell_width: w, cell_height: h, box_size: b } = geometry;
+        let n = self.board.len();
+        let mut put = |x: u16, y: u16, symbol: &str, style: Style| {
+            if x < area.right() && y < area.bottom() {
+                buf.get_mut(x, y).set_symbol(symbol).set_style(style);
+            }
+        };
+
+        for r in 0..n {
+            for c in 0..n {
+                let (x, y) = (left + geometry.offset(c, w), top + geometry.offset(r, h));
+                let style = self.cell_style(r, c);
+                for dy in 0..h {
+                    for dx in 0..w {
+                        put(x + dx, y + dy, " ", style);
+                    }
+                }
+                let marks = self.notes.map_or(0, |notes| notes[r][c]);
+                match digit_char(self.board[r][c]) {
+                    Some(digit) => put(x + w / 2, y + (h - 1) / 2, &digit.to_string(), style),
+                    // las notas van en una cuadrícula como la de una caja: 1-3 arriba, 7-9 abajo
+                    None if marks != 0 && h as usize >= b && w as usize >= b => {
+                        let step = if w as usize >= 2 * b - 1 { 2 } else { 1 };
+                        let from = x + (w - step * (b as u16 - 1) - 1) / 2;
+                        let note = if self.cursor == Some((r, c)) { style } else { style.fg(Color::Gray) };
+                        for i in (0..n).filter(|i| marks & (1 << i) != 0) {
+                            let digit = digit_char(i as u8 + 1).unwrap_or('?');
+                            put(from + step * (i % b) as u16, y + (i / b) as u16, &digit.to_string(), note);
+                        }
+                    }
+                    // si no cabe la cuadrícula (16x16) van seguidas, y un `+` dice que faltan
+                    None if marks != 0 => {
+                        let noted: Vec<char> = (0..n)
+                            .filter(|i| marks & (1 << i) != 0)
+                            .filter_map(|i| digit_char(i as u8 + 1))
+                            .collect();
+                        let room = (w * h) as usize;
+                        let note = if self.cursor == Some((r, c)) { style } else { style.fg(Color::Gray) };
+                        for (k, &digit) in noted.iter().enumerate().take(room) {
+                            let digit = if k + 1 == room && noted.len() > room { '+' } else { digit };
+                            put(x + (k % w as usize) as u16, y + (k / w as usize) as u16, &digit.to_string(), note);
+                        }
+                    }
+                    None => put(x + w / 2, y + (h - 1) / 2, "·", style.fg(Color::DarkGray)),
+                }
+            }
+        }
+
+        // líneas entre cajas, justo antes de la primera fila o columna de cada caja
+        let line = Style::default().fg(Color::DarkGray);
+        let columns: Vec<u16> = (1..b).map(|i| left + geometry.offset(i * b, w) - 1).collect();
+        for &x in &columns {
+            for y in top..top + height {
+                put(x, y, "│", line);
+            }
+        }
+        for y in (1..b).map(|i| top + geometry.offset(i * b, h) - 1) {
+            for x in left..left + width {
+                put(x, y, if columns.contains(&x) { "┼" } else { "─" }, line);
+            }
+        }
+
+        let footer_top = top + height + 1;
+        for (i, line) in self.footer.into_iter().enumerate() {
+            let y = footer_top + i as u16;
+            if y >= area.bottom() {
+                break;
+            }
+            let line_width = (line.width() as u16).min(area.width);
+            buf.set_line(area.x + (area.width - line_width) / 2, y, &line, line_width);
+        }
+    }
+}
+
+/// Placement of the board computed by [`Grid::geometry`].
+struct Geometry {
+    x: u16,
+    y: u16,
+    width: u16,
+    height: u16,
+    cell_width: u16,
+    cell_height: u16,
+    box_size: usize,
+}
+
+impl Geometry {
+    /// Distance from the board's edge to row or column `i` of cells `size` wide, counting the
+    /// lines between boxes before it.
+    fn offset(&self, i: usize, size: u16) -> u16 {
+        i as u16 * size + (i / self.box_size) as u16
+    }
+}
+
+/// How a value is shown: 1-9, then `A`-`G` for 10-16; `None` for an empty cell.
+pub fn digit_char(value: u8) -> Option<char> {
+    match value {
+        1..=9 => Some((b'0' + value) as char),
+        10..=16 => Some((b'A' + value - 10) as char),
+        _ => None,
+    }
+}
+
+/// Value of a digit key: 1-9, then `a`-`g` for 10-16 on 16x16 boards.
+pub fn digit_value(key: char) -> Option<u8> {
+    match key.to_ascii_lowercase() {
+        c @ '1'..='9' => Some(c as u8 - b'0'),
+        c @ 'a'..='g' => Some(c as u8 - b'a' + 10),
+        _ => None,
+    }
+}
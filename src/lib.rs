@@ -4,6 +4,7 @@
 // license that can be found in the LICENSE file or at
 // https://opensource.org/licenses/MIT.
 
+use std::fmt;
 use std::sync::{Arc, Mutex};
 use opentelemetry::trace::TracerProvider as _;
 use tracing_subscriber::{fmt::format::FmtSpan, prelude::*};
@@ -11,37 +12,271 @@ use tracing_subscriber::fmt::MakeWriter;
 use crate::sudoku::SudokuState;
 use self::sudoku::Sudoku;
 use self::sudoku::SudokuSize;
+use self::sudoku::Difficulty;
+use self::game::{Game, GameId, SolveRecord, Streak};
+use self::account::Session;
+use self::race::{RaceStatus, RoomId};
+use self::event::Event;
+use self::replay::Replay;
 
 /// This is the service definition. It looks a lot like a trait definition.
 /// It defines one RPC, hello, which takes one arg, name, and returns a String.
 #[tarpc::service]
 pub trait World {
     /// Returns a greeting for name.
-    async fn hello(name: String) -> String;
-    async fn sudoku(size: SudokuSize) -> Result<Sudoku, String>;
-    async fn is_solved(sudoku: Sudoku) -> SudokuState;
+    async fn hello(name: String) -> Result<String, ServiceError>;
+    async fn sudoku(size: SudokuSize, difficulty: Difficulty) -> Result<Sudoku, ServiceError>;
+    async fn is_solved(sudoku: Sudoku) -> Result<SudokuState, ServiceError>;
+    /// Starts a game on a new puzzle. The server keeps the solution; the returned game hides it.
+    async fn start(size: SudokuSize, difficulty: Difficulty) -> Result<Game, ServiceError>;
+    /// Returns the current state of a game, including one started before a server restart.
+    async fn resume(game_id: GameId) -> Result<Game, ServiceError>;
+    /// Writes `value` (0 clears) at `row`, `col` and returns the game with the move applied.
+    async fn place(game_id: GameId, row: u8, col: u8, value: u8) -> Result<Game, ServiceError>;
+    /// Takes back the caller's last move in the game, putting back the value it replaced, and
+    /// returns the game. Undo and redo history survives reloads and restarts.
+    async fn undo(game_id: GameId) -> Result<Game, ServiceError>;
+    /// Plays again the caller's last undone move, unless they placed something since.
+    async fn redo(game_id: GameId) -> Result<Game, ServiceError>;
+    /// Stops the game's clock, or starts it again if `paused` is false. A paused game takes no
+    /// moves. Cooperative games cannot be paused.
+    async fn pause(game_id: GameId, paused: bool) -> Result<Game, ServiceError>;
+    /// Adds `value` to the candidates noted in the empty cell at `row`, `col`, or takes it out if
+    /// it is there already; 0 clears them all. Returns the game.
+    async fn note(game_id: GameId, row: u8, col: u8, value: u8) -> Result<Game, ServiceError>;
+    /// Fastest solve per player for `size` and `difficulty`, best first, at most `limit` of them.
+    async fn leaderboard(size: SudokuSize, difficulty: Difficulty, limit: u32) -> Result<Vec<SolveRecord>, ServiceError>;
+    /// The logged-in player's fastest solve for `size` and `difficulty`, if any.
+    async fn personal_best(size: SudokuSize, difficulty: Difficulty) -> Result<Option<SolveRecord>, ServiceError>;
+    /// Starts today's daily puzzle of `size`, the same for every player on a UTC date. A logged-in
+    /// player who already started it gets their game back instead.
+    async fn daily(size: SudokuSize) -> Result<Game, ServiceError>;
+    /// Fastest solves of today's daily puzzle of `size`.
+    async fn daily_leaderboard(size: SudokuSize, limit: u32) -> Result<Vec<SolveRecord>, ServiceError>;
+    /// The logged-in player's run of consecutive days with a solved daily puzzle.
+    async fn streak() -> Result<Streak, ServiceError>;
+    /// Starts a cooperative game on a new puzzle: every player who joins plays the same board.
+    async fn start_coop(size: SudokuSize, difficulty: Difficulty) -> Result<Game, ServiceError>;
+    /// Joins a cooperative game that is not finished yet. A connection watching it as a spectator
+    /// cannot join it.
+    async fn join_coop(game_id: GameId) -> Result<Game, ServiceError>;
+    /// Watches any game, or a racer's game from `race_status`, without playing it: returns it as it
+    /// is and lets this connection follow its `next_events`. The solution is never sent, and the
+    /// connection can no longer `place` on that game.
+    async fn spectate(game_id: GameId) -> Result<Game, ServiceError>;
+    /// Everything needed to replay a game, finished or not: its puzzle and every move with its time
+    /// and player. Like `spectate`, open to anyone.
+    async fn replay(game_id: GameId) -> Result<Replay, ServiceError>;
+    /// The game's events after `since` (an `Event::seq`, 0 for all the server still keeps). If there
+    /// are none yet, waits for the next one or until the request is about to time out.
+    async fn next_events(game_id: GameId, since: u64) -> Result<Vec<Event>, ServiceError>;
+    /// Sends `text` to everyone following the game's events.
+    async fn chat(game_id: GameId, text: String) -> Result<(), ServiceError>;
+    /// Opens a race room on a new puzzle, with the caller as host and first racer.
+    async fn create_room(size: SudokuSize, difficulty: Difficulty) -> Result<RaceStatus, ServiceError>;
+    /// Joins a room whose race has not started yet.
+    async fn join_room(room: RoomId) -> Result<RaceStatus, ServiceError>;
+    /// Starts the race: every racer gets a game on the room's puzzle. Only the host may start it.
+    async fn start_race(room: RoomId) -> Result<RaceStatus, ServiceError>;
+    /// Waits until the room changes past `since` (a `RaceStatus::version`) or the request is about
+    /// to time out, and returns its status.
+    async fn race_status(room: RoomId, since: u64) -> Result<RaceStatus, ServiceError>;
+    /// Creates an account and logs this connection in as it.
+    async fn register(username: String, password: String) -> Result<Session, ServiceError>;
+    /// Checks the password and logs this connection in. The session token can be used later, from
+    /// any connection, with `authenticate`.
+    async fn login(username: String, password: String) -> Result<Session, ServiceError>;
+    /// Logs this connection in with a token from an earlier `register` or `login`.
+    async fn authenticate(token: String) -> Result<Session, ServiceError>;
+    /// Asks the server to shut down gracefully. Requires the server's admin token.
+    async fn shutdown(admin_token: String) -> Result<(), ServiceError>;
 }
 
+/// Errors returned by the `World` RPCs.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ServiceError {
+    /// The server already has `limit` connections from the client's address.
+    TooManyConnections { limit: usize },
+    /// The server already has `limit` connections in total.
+    ServerFull { limit: usize },
+    /// Wrong or missing admin token.
+    Unauthorized,
+    /// The puzzle could not be generated.
+    Generation(String),
+    /// The client asked for too many puzzles; it may retry after `retry_after_ms` milliseconds.
+    RateLimited { retry_after_ms: u64 },
+    /// The request's deadline passed before the server finished it.
+    Timeout,
+    /// No game with this id.
+    GameNotFound(GameId),
+    /// The move breaks a rule of the game (given cell, out of the board, game over...).
+    InvalidMove(String),
+    /// A chat message was empty or too long.
+    InvalidMessage(String),
+    /// The server could not read or write its storage.
+    Storage(String),
+    /// The username is already registered.
+    UsernameTaken(String),
+    /// Empty username or password, or one that is too long.
+    InvalidCredentials(String),
+    /// Wrong username or password.
+    LoginFailed,
+    /// Unknown or expired session token.
+    InvalidSession,
+    /// The game belongs to another player.
+    NotYourGame(GameId),
+    /// This connection watches the game as a spectator and cannot play it.
+    Spectating(GameId),
+    /// The RPC needs a logged-in player.
+    NotLoggedIn,
+    /// No race room with this id.
+    RoomNotFound(RoomId),
+    /// The room does not allow this right now (already started, not the host...).
+    RoomClosed(String),
+    /// The server is shutting down and ended a request that was waiting for updates.
+    ShuttingDown,
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceError::TooManyConnections { limit } => {
+                write!(f, "demasiadas conexiones desde esta dirección (máximo {limit})")
+            }
+            ServiceError::ServerFull { limit } => {
+                write!(f, "el servidor está lleno (máximo {limit} conexiones)")
+            }
+            ServiceError::Unauthorized => write!(f, "token de administración inválido"),
+            ServiceError::Generation(msg) => write!(f, "{msg}"),
+            ServiceError::RateLimited { retry_after_ms } => {
+                write!(f, "demasiadas peticiones, reintenta en {:.1} s", *retry_after_ms as f64 / 1000.0)
+            }
+            ServiceError::Timeout => write!(f, "tiempo de espera agotado"),
+            ServiceError::GameNotFound(id) => write!(f, "no existe la partida {id}"),
+            ServiceError::InvalidMove(msg) => write!(f, "jugada inválida: {msg}"),
+            ServiceError::InvalidMessage(msg) => write!(f, "mensaje inválido: {msg}"),
+            ServiceError::Storage(msg) => write!(f, "error de almacenamiento: {msg}"),
+            ServiceError::UsernameTaken(name) => write!(f, "el usuario {name} ya existe"),
+            ServiceError::InvalidCredentials(msg) => write!(f, "{msg}"),
+            ServiceError::LoginFailed => write!(f, "usuario o contraseña incorrectos"),
+            ServiceError::InvalidSession => write!(f, "sesión inválida o caducada"),
+            ServiceError::NotYourGame(id) => write!(f, "la partida {id} es de otro jugador"),
+            ServiceError::Spectating(id) => write!(f, "estás viendo la partida {id} como espectador"),
+            ServiceError::NotLoggedIn => write!(f, "hay que iniciar sesión"),
+            ServiceError::RoomNotFound(id) => write!(f, "no existe la sala {id}"),
+            ServiceError::RoomClosed(msg) => write!(f, "{msg}"),
+            ServiceError::ShuttingDown => write!(f, "el servidor se está apagando"),
+        }
+    }
+}
+
+impl std::error::Error for ServiceError {}
+
 pub mod sudoku {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Instant;
+    use rand::rngs::StdRng;
     use rand::seq::SliceRandom;
-    use rand::{rng};
+    use rand::{rng, Rng, SeedableRng};
     use tarpc::serde::{Deserialize, Serialize};
 
+    /// Stops a running [`Sudoku::generate`] or [`solve`], either explicitly or once a deadline
+    /// passes. The backtracking checks it at every step.
+    #[derive(Debug, Clone, Default)]
+    pub struct Cancel {
+        flag: Arc<AtomicBool>,
+        deadline: Option<Instant>,
+    }
+
+    impl Cancel {
+        /// A token that is only cancelled through [`Cancel::cancel`].
+        pub fn never() -> Cancel {
+            Cancel::default()
+        }
+
+        pub fn with_deadline(deadline: Instant) -> Cancel {
+            Cancel { flag: Arc::default(), deadline: Some(deadline) }
+        }
+
+        pub fn cancel(&self) {
+            self.flag.store(true, Ordering::Relaxed);
+        }
+
+        pub fn is_cancelled(&self) -> bool {
+            self.flag.load(Ordering::Relaxed) || self.deadline.is_some_and(|d| Instant::now() >= d)
+        }
+    }
+
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct Sudoku {
         pub board: Vec<Vec<u8>>,
         pub solved:  Vec<Vec<u8>>,
         pub sudoku_size: SudokuSize,
+        pub difficulty: Difficulty,
         pub state: SudokuState
     }
 
-    #[derive(Debug, Clone, clap::ValueEnum, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum, Serialize, Deserialize)]
     pub enum SudokuSize {
+        #[value(name = "4x4")]
         SUDOKU4X4,
+        #[value(name = "9x9")]
         SUDOKU9X9,
+        #[value(name = "16x16")]
         SUDOKU16X16,
     }
 
+    impl SudokuSize {
+        pub const ALL: [SudokuSize; 3] = [SudokuSize::SUDOKU4X4, SudokuSize::SUDOKU9X9, SudokuSize::SUDOKU16X16];
+
+        /// Side of one box: 2, 3 or 4.
+        pub fn box_size(&self) -> usize {
+            match self {
+                SudokuSize::SUDOKU4X4 => 2,
+                SudokuSize::SUDOKU9X9 => 3,
+                SudokuSize::SUDOKU16X16 => 4,
+            }
+        }
+
+        /// Size whose board has `n` rows, if any.
+        pub fn from_side(n: usize) -> Option<SudokuSize> {
+            match n {
+                4 => Some(SudokuSize::SUDOKU4X4),
+                9 => Some(SudokuSize::SUDOKU9X9),
+                16 => Some(SudokuSize::SUDOKU16X16),
+                _ => None,
+            }
+        }
+    }
+
+    /// How many cells are blanked out of the solved board.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, clap::ValueEnum, Serialize, Deserialize)]
+    pub enum Difficulty {
+        Easy,
+        #[default]
+        Medium,
+        Hard,
+    }
+
+    impl Difficulty {
+        pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+        fn empty_cells(&self, size: SudokuSize) -> usize {
+            match (size, self) {
+                (SudokuSize::SUDOKU4X4, Difficulty::Easy) => 4,
+                (SudokuSize::SUDOKU4X4, Difficulty::Medium) => 6,
+                (SudokuSize::SUDOKU4X4, Difficulty::Hard) => 8,
+                (SudokuSize::SUDOKU9X9, Difficulty::Easy) => 30,
+                (SudokuSize::SUDOKU9X9, Difficulty::Medium) => 40,
+                (SudokuSize::SUDOKU9X9, Difficulty::Hard) => 50,
+                (SudokuSize::SUDOKU16X16, Difficulty::Easy) => 90,
+                (SudokuSize::SUDOKU16X16, Difficulty::Medium) => 120,
+                (SudokuSize::SUDOKU16X16, Difficulty::Hard) => 150,
+            }
+        }
+    }
+
     #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
     pub enum SudokuState {
         Invalid,      // rompe reglas
@@ -50,33 +285,38 @@ pub mod sudoku {
     }
 
     impl Sudoku {
-        pub async fn generate_sudoku(size: SudokuSize) -> Result<Sudoku, String> {
-            let box_size = match size {
-                SudokuSize::SUDOKU4X4 => 2,
-                SudokuSize::SUDOKU9X9 => 3,
-                SudokuSize::SUDOKU16X16 => 4,
-            };
+        /// Generates a new puzzle. This is CPU-bound and blocks, so async callers should run it on
+        /// a blocking thread. Fails if `cancel` fires before the board is filled.
+        pub fn generate(size: SudokuSize, difficulty: Difficulty, cancel: &Cancel) -> Result<Sudoku, String> {
+            Sudoku::generate_with(size, difficulty, &mut rng(), cancel)
+        }
+
+        /// Like [`Sudoku::generate`], but the same `seed` always gives the same puzzle (for a given
+        /// build: the generator is rand's `StdRng`, which may change between rand releases).
+        pub fn generate_seeded(size: SudokuSize, difficulty: Difficulty, seed: u64, cancel: &Cancel) -> Result<Sudoku, String> {
+            Sudoku::generate_with(size, difficulty, &mut StdRng::seed_from_u64(seed), cancel)
+        }
+
+        fn generate_with<R: Rng + ?Sized>(size: SudokuSize, difficulty: Difficulty, rng: &mut R, cancel: &Cancel) -> Result<Sudoku, String> {
+            let box_size = size.box_size();
 
             let n = box_size * box_size;
 
             let mut board = vec![vec![0u8; n]; n];
 
-            if !fill_board(&mut board, box_size) {
+            if !fill_board(&mut board, box_size, rng, cancel) {
+                if cancel.is_cancelled() {
+                    return Err(String::from("Generación cancelada"));
+                }
                 return Err(String::from("No se pudo generar el sudoku"));
             }
 
             let solved = board.clone();
 
             // quitar celdas para hacer puzzle
-            let empty_cells = match size {
-                SudokuSize::SUDOKU4X4 => 6,
-                SudokuSize::SUDOKU9X9 => 40,
-                SudokuSize::SUDOKU16X16 => 120,
-            };
+            remove_cells(&mut board, difficulty.empty_cells(size), rng);
 
-            remove_cells(&mut board, empty_cells);
-
-            Ok(Sudoku { board, solved, sudoku_size: size, state: SudokuState::Incomplete })
+            Ok(Sudoku { board, solved, sudoku_size: size, difficulty, state: SudokuState::Incomplete })
         }
 
         pub fn check_user_board(
@@ -84,11 +324,7 @@ pub mod sudoku {
             user_board: &Vec<Vec<u8>>,
             size: SudokuSize,
         ) -> SudokuState {
-            let box_size = match size {
-                SudokuSize::SUDOKU4X4 => 2,
-                SudokuSize::SUDOKU9X9 => 3,
-                SudokuSize::SUDOKU16X16 => 4,
-            };
+            let box_size = size.box_size();
 
             let n = user_board.len();
 
@@ -111,6 +347,7 @@ pub mod sudoku {
             }
 
             // ---------- verificar si está completo ----------
+            #[allow(clippy::manual_contains)]
             let incomplete = user_board
                 .iter()
                 .any(|row| row.iter().any(|&x| x == 0));
@@ -128,19 +365,142 @@ pub mod sudoku {
         }
     }
 
-    fn fill_board(board: &mut Vec<Vec<u8>>, box_size: usize) -> bool {
+    /// Solves `board` (0 = empty) by backtracking, trying values in order so the result is
+    /// deterministic. Returns `None` if the board has no solution or an unsupported size, or if
+    /// `cancel` fires first.
+    pub fn solve(board: &[Vec<u8>], cancel: &Cancel) -> Option<Vec<Vec<u8>>> {
+        let size = SudokuSize::from_side(board.len())?;
+        if board.iter().any(|row| row.len() != board.len()) {
+            return None;
+        }
+
+        let box_size = size.box_size();
+        let mut solution = board.to_vec();
+
+        // las pistas dadas tienen que respetar las reglas
+        for row in 0..solution.len() {
+            for col in 0..solution.len() {
+                let num = solution[row][col];
+                if num == 0 {
+                    continue;
+                }
+                solution[row][col] = 0;
+                if num as usize > board.len() || !is_valid(&solution, row, col, num, box_size) {
+                    return None;
+                }
+                solution[row][col] = num;
+            }
+        }
+
+        if solve_board(&mut solution, box_size, cancel) {
+            Some(solution)
+        } else {
+            None
+        }
+    }
+
+    /// Parses a board written one row per line. Cells are separated by whitespace, or written
+    /// one character per cell when the row has no spaces. `.` and `0` are empty cells; 16x16
+    /// boards may use `A`-`G` for 10-16. No value may be larger than the side of the board.
+    pub fn parse_board(text: &str) -> Result<Vec<Vec<u8>>, String> {
+        let mut board = Vec::new();
+
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let cells: Vec<String> = if line.contains(char::is_whitespace) {
+                line.split_whitespace().map(String::from).collect()
+            } else {
+                line.chars().map(String::from).collect()
+            };
+
+            let row = cells
+                .iter()
+                .map(|cell| parse_cell(cell).ok_or_else(|| format!("celda inválida: {cell}")))
+                .collect::<Result<Vec<u8>, String>>()?;
+            board.push(row);
+        }
+
+        let n = board.len();
+        if SudokuSize::from_side(n).is_none() || board.iter().any(|row| row.len() != n) {
+            return Err(format!("el tablero debe ser de 4x4, 9x9 o 16x16 (leídas {n} filas)"));
+        }
+        if let Some(&value) = board.iter().flatten().find(|&&value| value as usize > n) {
+            return Err(format!("celda inválida: {value} en un tablero de {n}x{n}"));
+        }
+
+        Ok(board)
+    }
+
+    /// Writes a board in the format accepted by [`parse_board`].
+    pub fn format_board(board: &[Vec<u8>]) -> String {
+        let mut out = String::new();
+
+        for row in board {
+            let cells: Vec<String> = row
+                .iter()
+                .map(|&cell| if cell == 0 { ".".to_string() } else { cell.to_string() })
+                .collect();
+            out.push_str(&cells.join(" "));
+            out.push('\n');
+        }
+
+        out
+    }
+
+    fn parse_cell(cell: &str) -> Option<u8> {
+        match cell {
+            "." => Some(0),
+            _ => cell.parse().ok().or_else(|| {
+                let c = cell.chars().next()?.to_ascii_uppercase();
+                (cell.len() == 1 && ('A'..='G').contains(&c)).then(|| c as u8 - b'A' + 10)
+            }),
+        }
+    }
+
+    fn solve_board(board: &mut [Vec<u8>], box_size: usize, cancel: &Cancel) -> bool {
+        if cancel.is_cancelled() {
+            return false;
+        }
+
+        let n = board.len();
+
+        for row in 0..n {
+            for col in 0..n {
+                if board[row][col] == 0 {
+                    for num in 1..=n as u8 {
+                        if is_valid(board, row, col, num, box_size) {
+                            board[row][col] = num;
+                            if solve_board(board, box_size, cancel) {
+                                return true;
+                            }
+                            board[row][col] = 0;
+                        }
+                    }
+
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    fn fill_board<R: Rng + ?Sized>(board: &mut Vec<Vec<u8>>, box_size: usize, rng: &mut R, cancel: &Cancel) -> bool {
+        if cancel.is_cancelled() {
+            return false;
+        }
+
         let n = board.len();
 
         for row in 0..n {
             for col in 0..n {
                 if board[row][col] == 0 {
                     let mut nums: Vec<u8> = (1..=n as u8).collect();
-                    nums.shuffle(&mut rng());
+                    nums.shuffle(rng);
 
                     for num in nums {
                         if is_valid(board, row, col, num, box_size) {
                             board[row][col] = num;
-                            if fill_board(board, box_size) {
+                            if fill_board(board, box_size, rng, cancel) {
                                 return true;
                             }
                             board[row][col] = 0;
@@ -155,8 +515,9 @@ pub mod sudoku {
         true
     }
 
+    #[allow(clippy::needless_range_loop)]
     fn is_valid(
-        board: &Vec<Vec<u8>>,
+        board: &[Vec<u8>],
         row: usize,
         col: usize,
         num: u8,
@@ -192,24 +553,611 @@ pub mod sudoku {
         true
     }
 
-    fn remove_cells(board: &mut Vec<Vec<u8>>, empty: usize) {
+    fn remove_cells<R: Rng + ?Sized>(board: &mut [Vec<u8>], empty: usize, rng: &mut R) {
         let n = board.len();
 
-        let mut rng = rng();
-
         let mut cells: Vec<(usize, usize)> = (0..n)
             .flat_map(|r| (0..n).map(move |c| (r, c)))
             .collect();
 
-        cells.shuffle(&mut rng);
+        cells.shuffle(rng);
 
         for &(r, c) in cells.iter().take(empty) {
             board[r][c] = 0;
         }
     }
 
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// A valid solved board of side `box_size²`, with every value in it.
+        fn full_board(box_size: usize) -> Vec<Vec<u8>> {
+            let n = box_size * box_size;
+            (0..n).map(|r| (0..n).map(|c| ((r * box_size + r / box_size + c) % n + 1) as u8).collect()).collect()
+        }
 
+        /// `board` with some cells emptied, so `.` is written and read too.
+        fn with_holes(mut board: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+            let n = board.len();
+            for i in 0..n {
+                board[i][(i * 3) % n] = 0;
+            }
+            board
+        }
 
+        #[test]
+        fn format_then_parse_every_size() {
+            for size in SudokuSize::ALL {
+                let board = with_holes(full_board(size.box_size()));
+                assert_eq!(parse_board(&format_board(&board)), Ok(board), "{size:?}");
+            }
+        }
+
+        #[test]
+        fn letters_and_compact_rows_read_as_numbers() {
+            let board = with_holes(full_board(4));
+            let digit = |value: u8| match value {
+                0 => '.',
+                1..=9 => (b'0' + value) as char,
+                _ => (b'A' + value - 10) as char,
+            };
+            let compact = board.iter()
+                .map(|row| row.iter().map(|&value| digit(value)).collect::<String>())
+                .collect::<Vec<_>>()
+                .join("\n");
+            assert_eq!(parse_board(&compact), Ok(board.clone()));
+            assert_eq!(parse_board(&compact.to_lowercase()), Ok(board.clone()));
+
+            let spaced = compact.lines()
+                .map(|line| line.chars().map(String::from).collect::<Vec<_>>().join(" "))
+                .collect::<Vec<_>>()
+                .join("\n");
+            assert_eq!(parse_board(&spaced), Ok(board));
+        }
+
+        #[test]
+        fn zero_is_an_empty_cell() {
+            assert_eq!(parse_board("1 0 . 4\n0 0 0 0\n. . . .\n4 3 2 1"), Ok(vec![
+                vec![1, 0, 0, 4],
+                vec![0; 4],
+                vec![0; 4],
+                vec![4, 3, 2, 1],
+            ]));
+        }
+
+        #[test]
+        fn wrong_lengths_are_rejected() {
+            for text in [
+                "",
+                "1 2 3 4",
+                "1 2 3 4\n1 2 3 4\n1 2 3 4",
+                "1 2 3 4\n1 2 3 4\n1 2 3\n1 2 3 4",
+                "1 2 3 4 1\n1 2 3 4\n1 2 3 4\n1 2 3 4",
+                "12345\n1234\n1234\n1234\n1234",
+                &format_board(&full_board(3)).replacen("\n", "\n\n1 2 3 4 5 6 7 8 9\n", 1),
+            ] {
+                assert!(parse_board(text).is_err(), "aceptado:\n{text}");
+            }
+        }
+
+        #[test]
+        fn bad_cells_are_rejected() {
+            for cell in ["x", "-1", "H", "?", "1.5", "5", "17"] {
+                let text = format!("1 2 3 4\n{cell} . . .\n. . . .\n. . . .");
+                assert!(parse_board(&text).is_err(), "aceptada la celda {cell}");
+            }
+            let big = format_board(&full_board(4)).replacen("1", "17", 1);
+            assert!(parse_board(&big).is_err());
+            assert!(parse_board(&format_board(&full_board(3)).replacen("1", "A", 1)).is_err());
+        }
+    }
+}
+
+pub mod game {
+    use tarpc::serde::{Deserialize, Serialize};
+    use crate::event::{Event, EventKind};
+    use crate::sudoku::{Sudoku, SudokuState};
+
+    pub type GameId = u64;
+
+    /// A game as the server shows it to players: `sudoku.solved` is always empty.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Game {
+        pub id: GameId,
+        /// Current board and state.
+        pub sudoku: Sudoku,
+        /// The board as generated; its non-zero cells cannot be changed.
+        pub puzzle: Vec<Vec<u8>>,
+        /// UTC day (days since the Unix epoch) whose daily puzzle this is, if it is one.
+        pub daily: Option<u64>,
+        /// Who plays it, if it is a cooperative game.
+        pub coop: Option<Coop>,
+        /// Time played so far, as of when the server sent the game.
+        pub clock: Clock,
+        /// Values written that are not the ones in the solution.
+        pub mistakes: u32,
+        /// Same shape as the board: the candidates noted in each cell, with bit `v - 1` set when
+        /// `v` is one of them. Shared by everyone who plays the game.
+        pub notes: Vec<Vec<u16>>,
+    }
+
+    /// A game's clock. It runs from `start` to the move that solves the game, except while the
+    /// game is paused; that is the time that goes on the leaderboard.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Clock {
+        pub elapsed_ms: u64,
+        pub paused: bool,
+    }
+
+    /// The players of a cooperative game and who filled each cell.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Coop {
+        /// In the order they joined; the first one started the game.
+        pub players: Vec<String>,
+        /// Same shape as the board: 0 for givens and empty cells, otherwise `i + 1` where
+        /// `players[i]` wrote the value. Only that player may change or clear the cell.
+        pub filled_by: Vec<Vec<u8>>,
+    }
+
+    impl Game {
+        /// Brings the game up to date with an event received from `World::next_events`.
+        pub fn apply(&mut self, event: &Event) {
+            match &event.kind {
+                EventKind::Move { player, row, col, value } => {
+                    let (r, c) = (*row as usize, *col as usize);
+                    self.sudoku.board[r][c] = *value;
+                    clear_peer_notes(&mut self.notes, r, c, *value);
+                    if let Some(coop) = &mut self.coop {
+                        let author = player.as_ref()
+                            .and_then(|player| coop.players.iter().position(|name| name == player))
+                            .map_or(0, |i| i as u8 + 1);
+                        coop.filled_by[r][c] = if *value == 0 { 0 } else { author };
+                    }
+                }
+                EventKind::Joined { player } => {
+                    if let Some(coop) = &mut self.coop
+                        && !coop.players.contains(player) {
+                        coop.players.push(player.clone());
+                    }
+                }
+                EventKind::Completed { .. } => self.sudoku.state = SudokuState::Solved,
+                EventKind::Note { row, col, marks, .. } => self.notes[*row as usize][*col as usize] = *marks,
+                EventKind::Chat { .. } => {}
+            }
+        }
+    }
+
+    /// Clears the notes of `(row, col)` and takes `value` out of those of every cell in the same row,
+    /// column or box, now that it is written there. Returns the cells whose notes changed.
+    pub fn clear_peer_notes(notes: &mut [Vec<u16>], row: usize, col: usize, value: u8) -> Vec<(usize, usize)> {
+        let n = notes.len();
+        let b = (n as f64).sqrt() as usize;
+        if value == 0 || b == 0 {
+            return Vec::new();
+        }
+        let mark = 1 << (value - 1);
+        let mut changed = Vec::new();
+        for (r, cells) in notes.iter_mut().enumerate() {
+            for (c, marks) in cells.iter_mut().enumerate() {
+                let peer = r == row || c == col || (r / b == row / b && c / b == col / b);
+                // la propia celda ya tiene valor: sus notas sobran todas
+                let drop = if (r, c) == (row, col) { *marks } else { mark };
+                if peer && *marks & drop != 0 {
+                    *marks &= !drop;
+                    changed.push((r, c));
+                }
+            }
+        }
+        changed
+    }
+
+    /// One `place` applied to a game.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Move {
+        pub row: u8,
+        pub col: u8,
+        /// 0 clears the cell.
+        pub value: u8,
+        /// Milliseconds since the Unix epoch.
+        pub at_ms: u64,
+    }
+
+    /// A solved game on the leaderboard.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct SolveRecord {
+        pub username: String,
+        /// Time from `start` to the move that solved it.
+        pub millis: u64,
+        /// Milliseconds since the Unix epoch.
+        pub completed_at: u64,
+    }
+
+    /// Consecutive UTC days on which a player solved at least one daily puzzle.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Streak {
+        /// Run ending today, or yesterday if today's is still unsolved.
+        pub current: u32,
+        pub best: u32,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const ALL: u16 = 0b1111;
+
+        #[test]
+        fn placing_a_value_clears_it_from_row_column_and_box_peers() {
+            let mut notes = vec![vec![ALL; 4]; 4];
+            let mut changed = clear_peer_notes(&mut notes, 1, 2, 3);
+            changed.sort();
+
+            // fila 1, columna 2, la caja de arriba a la derecha y la propia celda
+            let peers = [(0, 2), (0, 3), (1, 0), (1, 1), (1, 2), (1, 3), (2, 2), (3, 2)];
+            assert_eq!(changed, peers);
+            for (r, cells) in notes.iter().enumerate() {
+                for (c, &marks) in cells.iter().enumerate() {
+                    let expected = match (r, c) {
+                        (1, 2) => 0,
+                        cell if peers.contains(&cell) => ALL & !0b0100,
+                        _ => ALL,
+                    };
+                    assert_eq!(marks, expected, "notas de ({r}, {c})");
+                }
+            }
+        }
+
+        #[test]
+        fn only_cells_that_had_the_note_are_reported() {
+            let mut notes = vec![vec![0; 4]; 4];
+            notes[0][0] = 0b0001;
+            notes[3][1] = 0b0001;
+            notes[2][3] = 0b0010;
+            // la propia celda pierde todas sus notas, no solo la de ese valor
+            notes[3][3] = 0b1000;
+
+            assert_eq!(clear_peer_notes(&mut notes, 3, 3, 1), [(3, 1), (3, 3)]);
+            assert_eq!(notes[0][0], 0b0001);
+            assert_eq!(notes[2][3], 0b0010);
+            assert_eq!(notes[3][1], 0);
+            assert_eq!(notes[3][3], 0);
+        }
+
+        #[test]
+        fn clearing_a_cell_keeps_the_notes() {
+            let mut notes = vec![vec![ALL; 4]; 4];
+            assert!(clear_peer_notes(&mut notes, 0, 0, 0).is_empty());
+            assert!(notes.iter().flatten().all(|&marks| marks == ALL));
+        }
+    }
+}
+
+pub mod event {
+    use std::fmt;
+    use tarpc::serde::{Deserialize, Serialize};
+
+    /// Something that happened in a game, as `World::next_events` reports it.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Event {
+        /// Position in the game's event stream. It only grows, also across server restarts, but
+        /// is not contiguous: a stream the server starts again begins above every earlier number.
+        pub seq: u64,
+        /// Milliseconds since the Unix epoch.
+        pub at_ms: u64,
+        pub kind: EventKind,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum EventKind {
+        /// A `place`; `player` is `None` for anonymous players.
+        Move { player: Option<String>, row: u8, col: u8, value: u8 },
+        Chat { player: String, text: String },
+        /// A player joined a cooperative game.
+        Joined { player: String },
+        /// The move by `player` solved the game.
+        Completed { player: Option<String> },
+        /// A `note`: `marks` are now the candidates of the cell, bit `v - 1` for `v`.
+        Note { player: Option<String>, row: u8, col: u8, marks: u16 },
+    }
+
+    impl fmt::Display for EventKind {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let name = |player: &Option<String>| player.clone().unwrap_or_else(|| "anónimo".to_string());
+            match self {
+                EventKind::Move { player, row, col, value: 0 } => write!(f, "{} borra ({row}, {col})", name(player)),
+                EventKind::Move { player, row, col, value } => write!(f, "{} pone {value} en ({row}, {col})", name(player)),
+                EventKind::Chat { player, text } => write!(f, "{player}: {text}"),
+                EventKind::Joined { player } => write!(f, "{player} entra en la partida"),
+                EventKind::Completed { player } => write!(f, "¡{} lo resuelve!", name(player)),
+                EventKind::Note { player, row, col, marks: 0 } => write!(f, "{} borra las notas de ({row}, {col})", name(player)),
+                EventKind::Note { player, row, col, marks } => {
+                    let noted: Vec<String> = (0..16).filter(|i| marks & (1 << i) != 0).map(|i| (i + 1).to_string()).collect();
+                    write!(f, "{} anota {} en ({row}, {col})", name(player), noted.join(" "))
+                }
+            }
+        }
+    }
+}
+
+pub mod replay {
+    use clap::ValueEnum;
+    use tarpc::serde::{Deserialize, Serialize};
+    use crate::game::{GameId, Move};
+    use crate::sudoku::{format_board, parse_board, Difficulty, SudokuSize};
+
+    /// A recorded game, as `World::replay` returns it and `export` writes it.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Replay {
+        pub game_id: GameId,
+        pub size: SudokuSize,
+        pub difficulty: Difficulty,
+        /// The board as generated.
+        pub puzzle: Vec<Vec<u8>>,
+        /// Milliseconds since the Unix epoch, like every time here.
+        pub started_at: u64,
+        pub finished_at: Option<u64>,
+        /// In the order they were played.
+        pub moves: Vec<ReplayMove>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct ReplayMove {
+        pub mv: Move,
+        /// `None` for anonymous players.
+        pub player: Option<String>,
+    }
+
+    const HEADER: &str = "# repetición de sudoku";
+
+    fn value_name<T: ValueEnum>(value: &T) -> String {
+        value.to_possible_value().expect("sin variantes ocultas").get_name().to_string()
+    }
+
+    impl Replay {
+        /// The board after the first `moves` moves.
+        pub fn board_at(&self, moves: usize) -> Vec<Vec<u8>> {
+            let mut board = self.puzzle.clone();
+            for ReplayMove { mv, .. } in self.moves.iter().take(moves) {
+                board[mv.row as usize][mv.col as usize] = mv.value;
+            }
+            board
+        }
+
+        /// Writes the replay as text: a header, `clave valor` lines, the puzzle in the
+        /// [`format_board`] format after `tablero`, and after `jugadas` one move per line as
+        /// `ms fila col valor jugador` (`-` for anonymous players and for a missing end time).
+        pub fn export(&self) -> String {
+            let mut out = format!(
+                "{HEADER}\npartida {}\ntamaño {}\ndificultad {}\ninicio {}\nfin {}\ntablero\n",
+                self.game_id,
+                value_name(&self.size),
+                value_name(&self.difficulty),
+                self.started_at,
+                self.finished_at.map_or("-".to_string(), |at| at.to_string()),
+            );
+            out.push_str(&format_board(&self.puzzle));
+            out.push_str("jugadas\n");
+            for ReplayMove { mv, player } in &self.moves {
+                out.push_str(&format!(
+                    "{} {} {} {} {}\n",
+                    mv.at_ms, mv.row, mv.col, mv.value, player.as_deref().unwrap_or("-"),
+                ));
+            }
+            out
+        }
+
+        /// Reads a replay written by [`Replay::export`].
+        pub fn import(text: &str) -> Result<Replay, String> {
+            let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+            if lines.next() != Some(HEADER) {
+                return Err("no es una repetición de sudoku".to_string());
+            }
+
+            let mut field = |key: &str| -> Result<String, String> {
+                let line = lines.next().ok_or_else(|| format!("falta `{key}`"))?;
+                line.strip_prefix(key)
+                    .map(|value| value.trim().to_string())
+                    .ok_or_else(|| format!("se esperaba `{key}` y se leyó `{line}`"))
+            };
+            let number = |value: String| value.parse::<u64>().map_err(|_| format!("número inválido: {value}"));
+
+            let game_id = number(field("partida")?)?;
+            let size = SudokuSize::from_str(&field("tamaño")?, true)?;
+            let difficulty = Difficulty::from_str(&field("dificultad")?, true)?;
+            let started_at = number(field("inicio")?)?;
+            let finished_at = match field("fin")?.as_str() {
+                "-" => None,
+                at => Some(number(at.to_string())?),
+            };
+            field("tablero")?;
+
+            let n = size.box_size().pow(2);
+            let rows: Vec<&str> = lines.by_ref().take(n).collect();
+            let puzzle = parse_board(&rows.join("\n"))?;
+            if puzzle.len() != n {
+                return Err(format!("el tablero no es de {n}x{n}"));
+            }
+            if lines.next() != Some("jugadas") {
+                return Err("falta `jugadas`".to_string());
+            }
+
+            let mut moves = Vec::new();
+            for line in lines {
+                let parts: Vec<&str> = line.split_whitespace().collect();
+                let [at, row, col, value, player] = parts.as_slice() else {
+                    return Err(format!("jugada inválida: {line}"));
+                };
+                let (Ok(at_ms), Ok(row), Ok(col), Ok(value)) = (at.parse(), row.parse::<u8>(), col.parse::<u8>(), value.parse::<u8>()) else {
+                    return Err(format!("jugada inválida: {line}"));
+                };
+                if row as usize >= n || col as usize >= n || value as usize > n {
+                    return Err(format!("jugada fuera del tablero: {line}"));
+                }
+                let player = (*player != "-").then(|| player.to_string());
+                moves.push(ReplayMove { mv: Move { row, col, value, at_ms }, player });
+            }
+
+            Ok(Replay { game_id, size, difficulty, puzzle, started_at, finished_at, moves })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn mv(at_ms: u64, row: u8, col: u8, value: u8, player: Option<&str>) -> ReplayMove {
+            ReplayMove { mv: Move { row, col, value, at_ms }, player: player.map(str::to_string) }
+        }
+
+        fn sample() -> Replay {
+            Replay {
+                game_id: 42,
+                size: SudokuSize::SUDOKU4X4,
+                difficulty: Difficulty::Hard,
+                puzzle: vec![
+                    vec![1, 0, 0, 4],
+                    vec![0, 4, 1, 0],
+                    vec![0, 1, 4, 0],
+                    vec![4, 0, 0, 1],
+                ],
+                started_at: 1_000,
+                finished_at: None,
+                moves: vec![
+                    mv(1_500, 0, 1, 2, Some("ana")),
+                    mv(2_000, 0, 2, 3, None),
+                    mv(2_500, 0, 1, 0, Some("ana")),
+                    mv(3_000, 0, 1, 2, Some("bob")),
+                ],
+            }
+        }
+
+        #[test]
+        fn export_then_import_gives_the_same_replay() {
+            let replay = sample();
+            assert_eq!(Replay::import(&replay.export()), Ok(replay.clone()));
+
+            let finished = Replay { finished_at: Some(4_000), moves: Vec::new(), ..replay };
+            assert_eq!(Replay::import(&finished.export()), Ok(finished));
+        }
+
+        #[test]
+        fn export_then_import_a_16x16_replay() {
+            let puzzle: Vec<Vec<u8>> = (0..16).map(|r| (0..16).map(|c| ((r * 4 + r / 4 + c) % 16 + 1) as u8).collect()).collect();
+            let replay = Replay {
+                size: SudokuSize::SUDOKU16X16,
+                puzzle,
+                moves: vec![mv(1_200, 15, 15, 16, Some("ana")), mv(1_300, 3, 9, 0, None)],
+                ..sample()
+            };
+            assert_eq!(Replay::import(&replay.export()), Ok(replay));
+        }
+
+        #[test]
+        fn board_at_applies_the_first_moves() {
+            let replay = sample();
+            let mut expected = replay.puzzle.clone();
+            assert_eq!(replay.board_at(0), expected);
+            expected[0][1] = 2;
+            assert_eq!(replay.board_at(1), expected);
+            expected[0][2] = 3;
+            assert_eq!(replay.board_at(2), expected);
+            expected[0][1] = 0;
+            assert_eq!(replay.board_at(3), expected);
+            expected[0][1] = 2;
+            assert_eq!(replay.board_at(4), expected);
+            assert_eq!(replay.board_at(100), expected);
+        }
+
+        #[test]
+        fn malformed_replays_are_rejected() {
+            let good = sample().export();
+            let mut cases = vec![
+                String::new(),
+                "hola".to_string(),
+                good.replacen(HEADER, "# otra cosa", 1),
+                good.replacen("partida 42", "partida cuarenta", 1),
+                good.replacen("tamaño 4x4", "tamaño 5x5", 1),
+                good.replacen("dificultad hard", "dificultad imposible", 1),
+                good.replacen("fin -", "fin nunca", 1),
+                good.replacen("tablero\n", "", 1),
+                good.replacen("1 . . 4\n", "", 1),
+                good.replacen("1 . . 4", "1 . x 4", 1),
+                good.replacen("jugadas\n", "", 1),
+                good.replace(" ana", ""),
+                good.replacen("1500 0 1 2", "1500 0 9 2", 1),
+                good.replacen("1500 0 1 2", "1500 0 1 7", 1),
+                good.replacen("1500 0 1 2", "ayer 0 1 2", 1),
+                good.replacen("1500 0 1 2", "1500 0 1 300", 1),
+            ];
+            // cortada en cualquier línea antes de las jugadas
+            let header_lines = good.lines().position(|line| line == "jugadas").unwrap();
+            cases.extend((0..=header_lines).map(|n| good.lines().take(n).collect::<Vec<_>>().join("\n")));
+
+            for case in cases {
+                assert!(Replay::import(&case).is_err(), "aceptada:\n{case}");
+            }
+        }
+    }
+}
+
+pub mod account {
+    use tarpc::serde::{Deserialize, Serialize};
+
+    pub type UserId = u64;
+
+    /// A logged-in player. `token` authenticates later connections through `World::authenticate`.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Session {
+        pub user_id: UserId,
+        pub username: String,
+        pub token: String,
+    }
+}
+
+pub mod race {
+    use tarpc::serde::{Deserialize, Serialize};
+    use crate::game::GameId;
+    use crate::sudoku::{Difficulty, SudokuSize};
+
+    pub type RoomId = u64;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum RaceState {
+        /// Players can still join.
+        Waiting,
+        Running,
+        /// Someone solved the puzzle.
+        Finished { winner: String },
+    }
+
+    /// One player in a race.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Racer {
+        pub username: String,
+        /// The racer's game, once the race has started.
+        pub game_id: Option<GameId>,
+        /// Empty cells of the puzzle the racer has written something in.
+        pub filled: usize,
+        /// Values written that do not match the solution.
+        pub mistakes: u32,
+        pub solved: bool,
+    }
+
+    /// Everything racers are shown about a room.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct RaceStatus {
+        pub room: RoomId,
+        /// Grows with every change, for `World::race_status`.
+        pub version: u64,
+        pub host: String,
+        pub size: SudokuSize,
+        pub difficulty: Difficulty,
+        /// Empty cells in the puzzle, the length of a full progress bar.
+        pub to_fill: usize,
+        pub state: RaceState,
+        pub racers: Vec<Racer>,
+    }
 }
 
 // LOGGING
@@ -282,6 +1230,61 @@ pub fn init_tracing(
     buffers: LogBuffers,
 ) -> anyhow::Result<opentelemetry_sdk::trace::SdkTracerProvider> {
 
+    let tracer_provider = tracer_provider(service_name);
+    let tracer = tracer_provider.tracer(service_name);
+
+    let ui_layer = tracing_subscriber::fmt::layer()
+        .with_writer(MultiWriter { buffers })
+        .with_ansi(false)
+        .with_target(true)
+        .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE);
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::from_default_env())
+        .with(ui_layer)
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .init();
+
+    Ok(tracer_provider)
+}
+
+/// Same as [`init_tracing`] but logs to stdout, for processes without a TUI.
+pub fn init_stdout_tracing(
+    service_name: &'static str,
+) -> anyhow::Result<opentelemetry_sdk::trace::SdkTracerProvider> {
+
+    let tracer_provider = tracer_provider(service_name);
+    let tracer = tracer_provider.tracer(service_name);
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::from_default_env())
+        .with(tracing_subscriber::fmt::layer().with_target(true))
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .init();
+
+    Ok(tracer_provider)
+}
+
+/// Installs a global OpenTelemetry meter provider exporting over OTLP every few seconds.
+pub fn init_metrics(service_name: &'static str) -> anyhow::Result<opentelemetry_sdk::metrics::SdkMeterProvider> {
+    let meter_provider = opentelemetry_sdk::metrics::SdkMeterProvider::builder()
+        .with_resource(
+            opentelemetry_sdk::Resource::builder()
+                .with_service_name(service_name)
+                .build(),
+        )
+        .with_periodic_exporter(
+            opentelemetry_otlp::MetricExporter::builder()
+                .with_tonic()
+                .build()?,
+        )
+        .build();
+
+    opentelemetry::global::set_meter_provider(meter_provider.clone());
+    Ok(meter_provider)
+}
+
+fn tracer_provider(service_name: &'static str) -> opentelemetry_sdk::trace::SdkTracerProvider {
     let tracer_provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
         .with_resource(
             opentelemetry_sdk::Resource::builder()
@@ -297,18 +1300,5 @@ pub fn init_tracing(
         .build();
 
     opentelemetry::global::set_tracer_provider(tracer_provider.clone());
-    let tracer = tracer_provider.tracer(service_name);
-
-    let ui_layer = tracing_subscriber::fmt::layer()
-        .with_writer(MultiWriter { buffers })
-        .with_target(true)
-        .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE);
-
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::from_default_env())
-        .with(ui_layer)
-        .with(tracing_opentelemetry::layer().with_tracer(tracer))
-        .init();
-
-    Ok(tracer_provider)
+    tracer_provider
 }
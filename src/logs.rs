@@ -0,0 +1,122 @@
+use std::str::FromStr;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Paragraph},
+};
+use tracing::Level;
+
+/// Level filters `cycle_level` goes through: everything, then at least each level.
+const LEVELS: [Option<Level>; 5] = [None, Some(Level::DEBUG), Some(Level::INFO), Some(Level::WARN), Some(Level::ERROR)];
+
+/// One log pane of the TUI: where it is scrolled to and which entries it shows.
+pub struct LogPane {
+    pub title: &'static str,
+    /// First line shown, counting wrapped lines, when not following.
+    top: usize,
+    /// Keeps the last line in view as entries arrive.
+    pub follow: bool,
+    /// Only entries containing this text, ignoring case.
+    pub filter: String,
+    /// Index in `LEVELS` of the least severe level shown.
+    level: usize,
+    /// Lines of the last frame: how many there were and how many fit, for scrolling.
+    lines: usize,
+    height: usize,
+}
+
+impl LogPane {
+    pub fn new(title: &'static str) -> Self {
+        LogPane { title, top: 0, follow: true, filter: String::new(), level: 0, lines: 0, height: 0 }
+    }
+
+    /// Scrolls `delta` lines down, or up if negative. Scrolling up stops following the end;
+    /// reaching it starts again.
+    pub fn scroll(&mut self, delta: isize) {
+        let bottom = self.bottom();
+        let top = if self.follow { bottom } else { self.top.min(bottom) };
+        self.top = top.saturating_add_signed(delta).min(bottom);
+        self.follow = self.top == bottom;
+    }
+
+    /// Scrolls a page down, or up if `up`.
+    pub fn page(&mut self, up: bool) {
+        let page = self.height.max(1) as isize;
+        self.scroll(if up { -page } else { page });
+    }
+
+    pub fn scroll_to_top(&mut self) {
+        self.top = 0;
+        self.follow = false;
+    }
+
+    /// Stops following the end where the view is, or jumps back to it.
+    pub fn toggle_follow(&mut self) {
+        if self.follow {
+            self.top = self.bottom();
+        }
+        self.follow = !self.follow;
+    }
+
+    /// Shows only more severe entries each time, back to all of them after errors.
+    pub fn cycle_level(&mut self) {
+        self.level = (self.level + 1) % LEVELS.len();
+    }
+
+    fn bottom(&self) -> usize {
+        self.lines.saturating_sub(self.height)
+    }
+
+    fn shows(&self, entry: &str) -> bool {
+        let level_ok = match LEVELS[self.level] {
+            Some(min) => entry_level(entry).is_some_and(|level| level <= min),
+            None => true,
+        };
+        level_ok && (self.filter.is_empty() || entry.to_lowercase().contains(&self.filter.to_lowercase()))
+    }
+
+    /// The entries that pass the filters, wrapped to fit `area` and scrolled. `focused` panes get
+    /// a highlighted border.
+    pub fn widget(&mut self, entries: &[String], area: Rect, focused: bool) -> Paragraph<'static> {
+        let width = area.width.saturating_sub(2).max(1) as usize;
+        let lines: Vec<String> = entries
+            .iter()
+            .filter(|entry| self.shows(entry))
+            .flat_map(|entry| entry.lines().flat_map(|line| wrap(line, width)).collect::<Vec<_>>())
+            .collect();
+        self.lines = lines.len();
+        self.height = area.height.saturating_sub(2) as usize;
+        if self.follow {
+            self.top = self.bottom();
+        }
+        self.top = self.top.min(self.bottom());
+
+        let mut title = self.title.to_string();
+        if !self.follow {
+            title.push_str(&format!(" [{}/{}]", self.top + self.height.min(self.lines), self.lines));
+        }
+        if let Some(level) = LEVELS[self.level] {
+            title.push_str(&format!(" ≥{level}"));
+        }
+        if !self.filter.is_empty() {
+            title.push_str(&format!(" /{}", self.filter));
+        }
+        let border = if focused { Style::default().fg(Color::Yellow) } else { Style::default() };
+
+        let shown = lines.into_iter().skip(self.top).take(self.height).map(Line::from).collect::<Vec<_>>();
+        Paragraph::new(shown).block(Block::default().title(title).borders(Borders::ALL).border_style(border))
+    }
+}
+
+/// Level of a formatted entry: the first word that names one, after the timestamp.
+fn entry_level(entry: &str) -> Option<Level> {
+    entry.split_whitespace().take(3).find_map(|word| Level::from_str(word).ok())
+}
+
+/// Splits `line` into pieces of at most `width` characters.
+fn wrap(line: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+    chars.chunks(width).map(|chunk| chunk.iter().collect()).collect()
+}
@@ -0,0 +1,476 @@
+use std::io::{self, Write};
+use clap::ValueEnum;
+use service::account::Session;
+use service::game::{Game, GameId};
+use service::race::{RaceState, RaceStatus};
+use service::sudoku::{format_board, Difficulty, SudokuSize, SudokuState};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use crate::client::RPCClient;
+
+const HELP: &str = "\
+Comandos:
+  nuevo 4|9|16 [easy|medium|hard]
+                      empieza una partida nueva en el servidor
+  diario 4|9|16       juega el sudoku del día (el mismo para todos)
+  reanudar ID         vuelve a una partida empezada antes
+  poner FILA COL VAL  escribe VAL en la celda (índices desde 0, 0 la borra)
+  anotar FILA COL VAL añade VAL a las notas de la celda vacía, o lo quita si ya estaba (0 las borra)
+  deshacer            quita tu última jugada y deja lo que había antes
+  rehacer             vuelve a hacer la última jugada deshecha
+  ver                 muestra el tablero y los errores
+  pausa               para el reloj de la partida; no se puede jugar hasta `seguir`
+  seguir              vuelve a poner en marcha el reloj
+  ranking             mejores tiempos del tamaño y dificultad de la partida actual
+  racha               días seguidos resolviendo el sudoku del día
+  verificar           pregunta al servidor si está resuelto
+  cooperativa nueva 4|9|16 [easy|medium|hard]
+                      empieza una partida que varios juegan a la vez (hace falta sesión)
+  cooperativa unir ID entra en la partida cooperativa ID; `verificar` trae las jugadas de los demás
+  mirar ID            sigue la partida ID como espectador (con `eventos`), sin poder jugarla
+  eventos             jugadas, mensajes y jugadores nuevos de la partida (espera si no hay)
+  decir TEXTO         manda un mensaje a los que siguen la partida
+  sala nueva 4|9|16 [easy|medium|hard]
+                      abre una sala de carrera (hace falta sesión)
+  sala unir ID        entra en la sala ID
+  sala empezar        empieza la carrera de tu sala (solo el anfitrión)
+  carrera             progreso de todos; al empezar carga tu partida
+  registrar USUARIO CONTRASEÑA
+                      crea una cuenta y entra con ella
+  entrar USUARIO CONTRASEÑA
+                      inicia sesión; las partidas nuevas quedan a tu nombre
+  sesion TOKEN        retoma una sesión iniciada antes
+  ayuda               muestra esta ayuda
+  salir";
+
+/// Plays against `client` reading one command per line from stdin, logged in as `session` if
+/// given.
+pub async fn run(client: RPCClient, session: Option<Session>) -> anyhow::Result<()> {
+    let mut game: Option<Game> = None;
+    let mut room: Option<RaceStatus> = None;
+    // último evento visto, de qué partida
+    let mut seen: (GameId, u64) = (0, 0);
+    let mut username = session.as_ref().map(|session| session.username.clone());
+
+    println!("{HELP}");
+    if let Some(username) = &username {
+        println!("conectado como {username}");
+    }
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+
+        let Some(line) = lines.next_line().await? else { break };
+
+        let args: Vec<&str> = line.split_whitespace().collect();
+        match args.as_slice() {
+            [] => continue,
+
+            ["nuevo", size, rest @ ..] if rest.len() <= 1 => {
+                let difficulty = match rest.first().map(|d| Difficulty::from_str(d, true)) {
+                    None => Difficulty::default(),
+                    Some(Ok(difficulty)) => difficulty,
+                    Some(Err(_)) => {
+                        println!("dificultad inválida: {}", rest[0]);
+                        continue;
+                    }
+                };
+                let Some(size) = parse_size(size) else {
+                    println!("tamaño inválido: {size}");
+                    continue;
+                };
+                match client.start_game(size, difficulty).await {
+                    Ok(new) => {
+                        println!("partida {}", new.id);
+                        print!("{}", format_board(&new.sudoku.board));
+                        game = Some(new);
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["diario", size] => {
+                let Some(size) = parse_size(size) else {
+                    println!("tamaño inválido: {size}");
+                    continue;
+                };
+                match client.daily(size).await {
+                    Ok(new) => {
+                        println!("partida {}", new.id);
+                        print!("{}", format_board(&new.sudoku.board));
+                        game = Some(new);
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["racha"] => match client.streak().await {
+                Ok(streak) => println!("racha: {} días (mejor {})", streak.current, streak.best),
+                Err(err) => println!("error: {err}"),
+            },
+
+            ["reanudar", id] => {
+                let Ok(id) = id.parse() else {
+                    println!("uso: reanudar ID");
+                    continue;
+                };
+                match client.resume_game(id).await {
+                    Ok(resumed) => {
+                        print!("{}", format_board(&resumed.sudoku.board));
+                        game = Some(resumed);
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["poner", row, col, value] => {
+                let Some(g) = &mut game else {
+                    println!("primero empieza una partida con `nuevo`");
+                    continue;
+                };
+                let (Ok(row), Ok(col), Ok(value)) = (row.parse::<u8>(), col.parse::<u8>(), value.parse::<u8>()) else {
+                    println!("uso: poner FILA COL VAL");
+                    continue;
+                };
+                match client.place(g, row, col, value).await {
+                    Ok(()) if g.sudoku.state == SudokuState::Solved => println!("¡Resuelto en {:.1} s!", g.clock.elapsed_ms as f64 / 1000.0),
+                    Ok(()) => {}
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["anotar", row, col, value] => {
+                let Some(g) = &mut game else {
+                    println!("primero empieza una partida con `nuevo`");
+                    continue;
+                };
+                let (Ok(row), Ok(col), Ok(value)) = (row.parse::<u8>(), col.parse::<u8>(), value.parse::<u8>()) else {
+                    println!("uso: anotar FILA COL VAL");
+                    continue;
+                };
+                match client.note(g, row, col, value).await {
+                    Ok(()) => {
+                        let marks = g.notes[row as usize][col as usize];
+                        let noted: Vec<String> = (1..=16).filter(|v| marks & (1 << (v - 1)) != 0).map(|v| v.to_string()).collect();
+                        println!("notas en ({row}, {col}): {}", if noted.is_empty() { "ninguna".to_string() } else { noted.join(" ") });
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            [command @ ("deshacer" | "rehacer")] => {
+                let Some(g) = &mut game else {
+                    println!("primero empieza una partida con `nuevo`");
+                    continue;
+                };
+                let result = if *command == "deshacer" { client.undo(g).await } else { client.redo(g).await };
+                match result {
+                    Ok(()) if g.sudoku.state == SudokuState::Solved => println!("¡Resuelto en {:.1} s!", g.clock.elapsed_ms as f64 / 1000.0),
+                    Ok(()) => print!("{}", format_board(&g.sudoku.board)),
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            [command @ ("pausa" | "seguir")] => {
+                let Some(g) = &mut game else {
+                    println!("primero empieza una partida con `nuevo`");
+                    continue;
+                };
+                match client.pause(g, *command == "pausa").await {
+                    Ok(()) if g.clock.paused => println!("en pausa a los {} s", g.clock.elapsed_ms / 1000),
+                    Ok(()) => println!("en marcha desde los {} s", g.clock.elapsed_ms / 1000),
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["ver"] => match &game {
+                Some(g) => {
+                    print!("{}", format_board(&g.sudoku.board));
+                    println!("errores: {}", g.mistakes);
+                }
+                None => println!("No hay sudoku"),
+            },
+
+            ["verificar"] => {
+                if let Some(g) = &mut game {
+                    match client.resume_game(g.id).await {
+                        Ok(current) => {
+                            match current.sudoku.state {
+                                SudokuState::Solved => println!("¡Resuelto!"),
+                                ref state => println!("{state:?}"),
+                            }
+                            *g = current;
+                        }
+                        Err(err) => println!("error: {err}"),
+                    }
+                }
+            }
+
+            ["cooperativa", "nueva", size, rest @ ..] if rest.len() <= 1 => {
+                let difficulty = match rest.first().map(|d| Difficulty::from_str(d, true)) {
+                    None => Difficulty::default(),
+                    Some(Ok(difficulty)) => difficulty,
+                    Some(Err(_)) => {
+                        println!("dificultad inválida: {}", rest[0]);
+                        continue;
+                    }
+                };
+                let Some(size) = parse_size(size) else {
+                    println!("tamaño inválido: {size}");
+                    continue;
+                };
+                match client.start_coop(size, difficulty).await {
+                    Ok(new) => {
+                        println!("partida {}; los demás entran con `cooperativa unir {}`", new.id, new.id);
+                        print!("{}", format_board(&new.sudoku.board));
+                        game = Some(new);
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["cooperativa", "unir", id] => {
+                let Ok(id) = id.parse() else {
+                    println!("uso: cooperativa unir ID");
+                    continue;
+                };
+                match client.join_coop(id).await {
+                    Ok(joined) => {
+                        if let Some(coop) = &joined.coop {
+                            println!("jugadores: {}", coop.players.join(", "));
+                        }
+                        print!("{}", format_board(&joined.sudoku.board));
+                        game = Some(joined);
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["mirar", id] => {
+                let Ok(id) = id.parse() else {
+                    println!("uso: mirar ID");
+                    continue;
+                };
+                match client.spectate(id).await {
+                    Ok(watched) => {
+                        print!("{}", format_board(&watched.sudoku.board));
+                        game = Some(watched);
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["eventos"] => {
+                let Some(g) = &mut game else {
+                    println!("primero empieza una partida con `nuevo`");
+                    continue;
+                };
+                let since = if seen.0 == g.id { seen.1 } else { 0 };
+                match client.next_events(g.id, since).await {
+                    Ok(events) if events.is_empty() => println!("nada nuevo"),
+                    Ok(events) => {
+                        for event in &events {
+                            g.apply(event);
+                            println!("{}", event.kind);
+                        }
+                        seen = (g.id, events.last().map_or(since, |event| event.seq));
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["decir", ..] => {
+                let Some(g) = &game else {
+                    println!("primero empieza una partida con `nuevo`");
+                    continue;
+                };
+                let text = line.trim_start().trim_start_matches("decir").trim().to_string();
+                if let Err(err) = client.chat(g.id, text).await {
+                    println!("error: {err}");
+                }
+            }
+
+            ["sala", "nueva", size, rest @ ..] if rest.len() <= 1 => {
+                let difficulty = match rest.first().map(|d| Difficulty::from_str(d, true)) {
+                    None => Difficulty::default(),
+                    Some(Ok(difficulty)) => difficulty,
+                    Some(Err(_)) => {
+                        println!("dificultad inválida: {}", rest[0]);
+                        continue;
+                    }
+                };
+                let Some(size) = parse_size(size) else {
+                    println!("tamaño inválido: {size}");
+                    continue;
+                };
+                match client.create_room(size, difficulty).await {
+                    Ok(status) => {
+                        println!("sala {} abierta; los demás entran con `sala unir {}`", status.room, status.room);
+                        room = Some(status);
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["sala", "unir", id] => {
+                let Ok(id) = id.parse() else {
+                    println!("uso: sala unir ID");
+                    continue;
+                };
+                match client.join_room(id).await {
+                    Ok(status) => {
+                        print_race(&status);
+                        room = Some(status);
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["sala", "empezar"] => {
+                let Some(current) = &room else {
+                    println!("primero abre o únete a una sala");
+                    continue;
+                };
+                match client.start_race(current.room).await {
+                    Ok(status) => {
+                        game = load_racer_game(&client, &status, username.as_deref()).await.or(game);
+                        room = Some(status);
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["carrera"] => {
+                let Some(current) = &room else {
+                    println!("primero abre o únete a una sala");
+                    continue;
+                };
+                // version 0 nunca es posterior a la actual: responde sin esperar
+                match client.race_status(current.room, 0).await {
+                    Ok(status) => {
+                        print_race(&status);
+                        let playing = game.as_ref().map(|g| g.id);
+                        let mine = racer_game(&status, username.as_deref());
+                        if mine.is_some() && mine != playing {
+                            game = load_racer_game(&client, &status, username.as_deref()).await.or(game);
+                        }
+                        room = Some(status);
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["ranking"] => {
+                let Some(g) = &game else {
+                    println!("primero empieza una partida con `nuevo`");
+                    continue;
+                };
+                let (size, difficulty) = (g.sudoku.sudoku_size, g.sudoku.difficulty);
+                let records = match g.daily {
+                    Some(_) => client.daily_leaderboard(size, 10).await,
+                    None => client.leaderboard(size, difficulty, 10).await,
+                };
+                match records {
+                    Ok(records) if records.is_empty() => println!("nadie lo ha resuelto aún"),
+                    Ok(records) => {
+                        for (i, record) in records.iter().enumerate() {
+                            println!("{:>2}. {:<18} {:.1} s", i + 1, record.username, record.millis as f64 / 1000.0);
+                        }
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+                if g.daily.is_none() {
+                    match client.personal_best(size, difficulty).await {
+                        Ok(Some(best)) => println!("tu mejor tiempo: {:.1} s", best.millis as f64 / 1000.0),
+                        Ok(None) => println!("aún no lo has resuelto"),
+                        Err(_) => {}
+                    }
+                }
+            }
+
+            ["registrar", user, password] => {
+                match client.register(user.to_string(), password.to_string()).await {
+                    Ok(session) => {
+                        println!("cuenta creada, sesión: {}", session.token);
+                        username = Some(session.username);
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["entrar", user, password] => {
+                match client.login(user.to_string(), password.to_string()).await {
+                    Ok(session) => {
+                        println!("hola, {}; sesión: {}", session.username, session.token);
+                        username = Some(session.username);
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["sesion", token] => {
+                match client.authenticate(token.to_string()).await {
+                    Ok(session) => {
+                        println!("conectado como {}", session.username);
+                        username = Some(session.username);
+                    }
+                    Err(err) => println!("error: {err}"),
+                }
+            }
+
+            ["ayuda"] => println!("{HELP}"),
+            ["salir"] | ["q"] => break,
+            _ => println!("comando desconocido, escribe `ayuda`"),
+        }
+    }
+
+    Ok(())
+}
+
+fn print_race(status: &RaceStatus) {
+    match &status.state {
+        RaceState::Waiting => println!("sala {} de {}: esperando a que empiece", status.room, status.host),
+        RaceState::Running => println!("sala {}: en carrera", status.room),
+        RaceState::Finished { winner } => println!("sala {}: ¡gana {winner}!", status.room),
+    }
+    for racer in &status.racers {
+        println!(
+            "  {:<18} {:>3}/{:<3} {} fallos{}",
+            racer.username,
+            racer.filled,
+            status.to_fill,
+            racer.mistakes,
+            if racer.solved { "  resuelto" } else { "" },
+        );
+    }
+}
+
+/// The game `username` plays in the race, once it has started.
+fn racer_game(status: &RaceStatus, username: Option<&str>) -> Option<GameId> {
+    status.racers.iter().find(|racer| Some(racer.username.as_str()) == username)?.game_id
+}
+
+async fn load_racer_game(client: &RPCClient, status: &RaceStatus, username: Option<&str>) -> Option<Game> {
+    let id = racer_game(status, username)?;
+    match client.resume_game(id).await {
+        Ok(game) => {
+            println!("¡empieza la carrera! partida {id}");
+            print!("{}", format_board(&game.sudoku.board));
+            Some(game)
+        }
+        Err(err) => {
+            println!("error: {err}");
+            None
+        }
+    }
+}
+
+fn parse_size(size: &str) -> Option<SudokuSize> {
+    match size {
+        "4" => Some(SudokuSize::SUDOKU4X4),
+        "9" => Some(SudokuSize::SUDOKU9X9),
+        "16" => Some(SudokuSize::SUDOKU16X16),
+        _ => None,
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use opentelemetry::{global, metrics::Counter, KeyValue};
+use service::sudoku::{Difficulty, Sudoku, SudokuSize};
+use tokio::{sync::Notify, time};
+use crate::server::ShutdownHandle;
+use crate::workers::BlockingPool;
+
+type Slot = (SudokuSize, Difficulty);
+
+/// Keeps `depth` ready puzzles for every size and difficulty so `World::sudoku` can answer without
+/// generating. A background task tops the slots up, at most `refill_per_minute` puzzles a minute.
+pub struct PuzzlePool {
+    depth: usize,
+    refill_interval: Duration,
+    slots: Mutex<HashMap<Slot, VecDeque<Sudoku>>>,
+    /// Woken when a puzzle is taken, so an idle refill task starts again.
+    wanted: Notify,
+    hits: Counter<u64>,
+    misses: Counter<u64>,
+    generated: Counter<u64>,
+}
+
+fn attributes((size, difficulty): Slot) -> [KeyValue; 2] {
+    [
+        KeyValue::new("size", format!("{size:?}")),
+        KeyValue::new("difficulty", format!("{difficulty:?}")),
+    ]
+}
+
+impl PuzzlePool {
+    pub fn new(depth: usize, refill_per_minute: u32) -> Arc<Self> {
+        let meter = global::meter("sudoku-server");
+
+        let pool = Arc::new(PuzzlePool {
+            depth,
+            refill_interval: Duration::from_secs_f64(60.0 / refill_per_minute.max(1) as f64),
+            slots: Mutex::new(HashMap::new()),
+            wanted: Notify::new(),
+            hits: meter.u64_counter("puzzle_pool.hits")
+                .with_description("Puzzles served from the pool")
+                .build(),
+            misses: meter.u64_counter("puzzle_pool.misses")
+                .with_description("Requests that found the pool empty and generated on demand")
+                .build(),
+            generated: meter.u64_counter("puzzle_pool.generated")
+                .with_description("Puzzles generated by the refill task")
+                .build(),
+        });
+
+        let weak = Arc::downgrade(&pool);
+        meter.u64_observable_gauge("puzzle_pool.depth")
+            .with_description("Ready puzzles per size and difficulty")
+            .with_callback(move |observer| {
+                let Some(pool) = weak.upgrade() else { return };
+                for (slot, puzzles) in pool.slots.lock().unwrap().iter() {
+                    observer.observe(puzzles.len() as u64, &attributes(*slot));
+                }
+            })
+            .build();
+
+        pool
+    }
+
+    /// Takes a ready puzzle, if the pool has one.
+    pub fn take(&self, size: SudokuSize, difficulty: Difficulty) -> Option<Sudoku> {
+        let puzzle = self.slots.lock().unwrap()
+            .get_mut(&(size, difficulty))
+            .and_then(VecDeque::pop_front);
+
+        match puzzle {
+            Some(_) => self.hits.add(1, &attributes((size, difficulty))),
+            None => self.misses.add(1, &attributes((size, difficulty))),
+        }
+        self.wanted.notify_one();
+        puzzle
+    }
+
+    /// The slot furthest from full, if any is below `depth`.
+    fn emptiest(&self) -> Option<Slot> {
+        let slots = self.slots.lock().unwrap();
+        SudokuSize::ALL
+            .iter()
+            .flat_map(|&size| Difficulty::ALL.iter().map(move |&difficulty| (size, difficulty)))
+            .map(|slot| (slot, slots.get(&slot).map_or(0, VecDeque::len)))
+            .filter(|&(_, len)| len < self.depth)
+            .min_by_key(|&(_, len)| len)
+            .map(|(slot, _)| slot)
+    }
+
+    /// Keeps the pool full until `shutdown`, which also stops a generation half done.
+    pub async fn refill(self: Arc<Self>, generators: BlockingPool, shutdown: ShutdownHandle) {
+        let cancel = shutdown.cancel();
+        loop {
+            let Some(slot) = self.emptiest() else {
+                tokio::select! {
+                    _ = self.wanted.notified() => continue,
+                    _ = shutdown.wait() => return,
+                }
+            };
+
+            let (size, difficulty) = slot;
+            let job = cancel.clone();
+            match generators.run(move || Sudoku::generate(size, difficulty, &job)).await {
+                Err(_) if cancel.is_cancelled() => return,
+                Ok(puzzle) => {
+                    self.slots.lock().unwrap().entry(slot).or_default().push_back(puzzle);
+                    self.generated.add(1, &attributes(slot));
+                }
+                Err(err) => tracing::warn!(target: "server", "No se pudo rellenar el pool: {err}"),
+            }
+
+            tokio::select! {
+                _ = time::sleep(self.refill_interval) => {}
+                _ = shutdown.wait() => return,
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use service::account::{Session, UserId};
+use service::game::GameId;
+use service::race::{RaceState, RaceStatus, Racer, RoomId};
+use service::sudoku::{Sudoku, SudokuState};
+use service::ServiceError;
+use tokio::sync::watch;
+use tokio::time;
+use crate::games::{Games, Placement, LONG_POLL_MARGIN};
+
+/// Racers a room takes at most.
+const MAX_RACERS: usize = 8;
+
+/// How long a finished room stays around so racers can see the result.
+const FINISHED_ROOM_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// How long a room stays around with nothing happening in it, whatever its state: never started,
+/// or left unfinished by its racers.
+const IDLE_ROOM_TTL: Duration = Duration::from_secs(60 * 60);
+
+struct Room {
+    host: UserId,
+    puzzle: Sudoku,
+    /// Same order as `RaceStatus::racers`.
+    members: Vec<UserId>,
+    status: watch::Sender<RaceStatus>,
+    /// The racers' games are being created; nobody may join or start it meanwhile.
+    starting: bool,
+    finished_at: Option<Instant>,
+    /// Last time it changed.
+    active_at: Instant,
+}
+
+impl Room {
+    /// Applies `change` and bumps the version, waking everyone waiting on the room.
+    fn update(&mut self, change: impl FnOnce(&mut RaceStatus)) {
+        self.active_at = Instant::now();
+        self.status.send_modify(|status| {
+            change(status);
+            status.version += 1;
+        });
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    next_id: RoomId,
+    rooms: HashMap<RoomId, Room>,
+    /// Room and racer index of each game started by a race.
+    by_game: HashMap<GameId, (RoomId, usize)>,
+}
+
+/// Race rooms. They live in memory only; the racers' games are ordinary games and are stored as
+/// such.
+#[derive(Default)]
+pub struct Races {
+    inner: Mutex<Inner>,
+}
+
+fn racer(username: &str) -> Racer {
+    Racer { username: username.to_string(), game_id: None, filled: 0, mistakes: 0, solved: false }
+}
+
+impl Races {
+    pub fn create(&self, host: &Session, puzzle: Sudoku) -> RaceStatus {
+        let mut inner = self.inner.lock().unwrap();
+
+        let expired: Vec<RoomId> = inner.rooms.iter()
+            .filter(|(_, room)| {
+                let finished = room.finished_at.is_some_and(|t| t.elapsed() > FINISHED_ROOM_TTL);
+                !room.starting && (finished || room.active_at.elapsed() > IDLE_ROOM_TTL)
+            })
+            .map(|(&id, _)| id)
+            .collect();
+        for id in expired {
+            inner.rooms.remove(&id);
+            inner.by_game.retain(|_, (room, _)| *room != id);
+        }
+
+        inner.next_id += 1;
+        let id = inner.next_id;
+        let status = RaceStatus {
+            room: id,
+            version: 0,
+            host: host.username.clone(),
+            size: puzzle.sudoku_size,
+            difficulty: puzzle.difficulty,
+            to_fill: puzzle.board.iter().flatten().filter(|&&cell| cell == 0).count(),
+            state: RaceState::Waiting,
+            racers: vec![racer(&host.username)],
+        };
+        inner.rooms.insert(id, Room {
+            host: host.user_id,
+            puzzle,
+            members: vec![host.user_id],
+            status: watch::Sender::new(status.clone()),
+            starting: false,
+            finished_at: None,
+            active_at: Instant::now(),
+        });
+        tracing::info!(target: "server", "Sala {id} abierta por {}", host.username);
+        status
+    }
+
+    pub fn join(&self, id: RoomId, session: &Session) -> Result<RaceStatus, ServiceError> {
+        let mut inner = self.inner.lock().unwrap();
+        let room = inner.rooms.get_mut(&id).ok_or(ServiceError::RoomNotFound(id))?;
+
+        if !room.members.contains(&session.user_id) {
+            if room.starting || room.status.borrow().state != RaceState::Waiting {
+                return Err(ServiceError::RoomClosed("la carrera ya empezó".into()));
+            }
+            if room.members.len() >= MAX_RACERS {
+                return Err(ServiceError::RoomClosed(format!("la sala está llena (máximo {MAX_RACERS})")));
+            }
+            room.members.push(session.user_id);
+            room.update(|status| status.racers.push(racer(&session.username)));
+            tracing::info!(target: "server", "{} entra en la sala {id}", session.username);
+        }
+        Ok(room.status.borrow().clone())
+    }
+
+    /// Starts a game on the room's puzzle for every racer.
+    pub async fn start(&self, id: RoomId, user: UserId, games: &Games) -> Result<RaceStatus, ServiceError> {
+        let (puzzle, members) = {
+            let mut inner = self.inner.lock().unwrap();
+            let room = inner.rooms.get_mut(&id).ok_or(ServiceError::RoomNotFound(id))?;
+
+            if room.host != user {
+                return Err(ServiceError::RoomClosed("solo el anfitrión puede empezar la carrera".into()));
+            }
+            if room.starting || room.status.borrow().state != RaceState::Waiting {
+                return Err(ServiceError::RoomClosed("la carrera ya empezó".into()));
+            }
+            room.starting = true;
+            (room.puzzle.clone(), room.members.clone())
+        };
+
+        // las partidas se crean sin tener bloqueadas las salas
+        let mut game_ids = Vec::with_capacity(members.len());
+        let mut failed = None;
+        for member in members {
+            match games.start(puzzle.clone(), Some(member)).await {
+                Ok(game) => game_ids.push(game.id),
+                Err(err) => {
+                    failed = Some(err);
+                    break;
+                }
+            }
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let room = inner.rooms.get_mut(&id).ok_or(ServiceError::RoomNotFound(id))?;
+        room.starting = false;
+        if let Some(err) = failed {
+            return Err(err);
+        }
+
+        room.update(|status| {
+            status.state = RaceState::Running;
+            for (racer, &game_id) in status.racers.iter_mut().zip(&game_ids) {
+                racer.game_id = Some(game_id);
+            }
+        });
+        let status = room.status.borrow().clone();
+
+        for (i, game_id) in game_ids.into_iter().enumerate() {
+            inner.by_game.insert(game_id, (id, i));
+        }
+        tracing::info!(target: "server", "Empieza la carrera de la sala {id}");
+        Ok(status)
+    }
+
+    /// Updates the racer playing `game_id`, if it belongs to a running race.
+    pub fn on_move(&self, game_id: GameId, placement: &Placement) {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(&(id, index)) = inner.by_game.get(&game_id) else { return };
+        let Some(room) = inner.rooms.get_mut(&id) else { return };
+        if room.status.borrow().state != RaceState::Running {
+            return;
+        }
+
+        let solved = placement.state == SudokuState::Solved;
+        room.update(|status| {
+            let racer = &mut status.racers[index];
+            racer.filled = placement.filled;
+            racer.mistakes += placement.mistake as u32;
+            racer.solved = solved;
+            if solved {
+                tracing::info!(target: "server", "{} gana la carrera de la sala {id}", racer.username);
+                status.state = RaceState::Finished { winner: racer.username.clone() };
+            }
+        });
+        if solved {
+            room.finished_at = Some(Instant::now());
+        }
+    }
+
+    /// The room's status once its version is past `since`, or as it is when `deadline` is close.
+    pub async fn wait(&self, id: RoomId, since: u64, deadline: Instant) -> Result<RaceStatus, ServiceError> {
+        let mut updates = {
+            let inner = self.inner.lock().unwrap();
+            inner.rooms.get(&id).ok_or(ServiceError::RoomNotFound(id))?.status.subscribe()
+        };
+
+        let until = deadline.checked_sub(LONG_POLL_MARGIN).unwrap_or(deadline);
+        let _ = time::timeout_at(until.into(), updates.wait_for(|status| status.version > since)).await;
+        let status = updates.borrow().clone();
+        Ok(status)
+    }
+}
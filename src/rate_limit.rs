@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use service::account::UserId;
+use service::ServiceError;
+
+/// Buckets kept before idle, full ones are dropped.
+const MAX_IDLE_BUCKETS: usize = 1024;
+
+/// Who a request is charged to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RateKey {
+    Peer(IpAddr),
+    /// A logged-in player, wherever they connect from.
+    User(UserId),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket per [`RateKey`]: `burst` requests at once, refilled at `per_minute`.
+pub struct RateLimiter {
+    burst: f64,
+    refill_per_sec: f64,
+    buckets: Mutex<HashMap<RateKey, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(burst: u32, per_minute: u32) -> Self {
+        RateLimiter {
+            burst: burst.max(1) as f64,
+            refill_per_sec: per_minute as f64 / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes one token from `key`'s bucket, or says how long until one is available.
+    pub fn check(&self, key: RateKey) -> Result<(), ServiceError> {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: RateKey, now: Instant) -> Result<(), ServiceError> {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() > MAX_IDLE_BUCKETS {
+            let (burst, rate) = (self.burst, self.refill_per_sec);
+            buckets.retain(|_, b| b.tokens + now.duration_since(b.updated).as_secs_f64() * rate < burst);
+        }
+
+        let bucket = buckets.entry(key).or_insert(Bucket { tokens: self.burst, updated: now });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        let wait = if self.refill_per_sec > 0.0 {
+            Duration::from_secs_f64((1.0 - bucket.tokens) / self.refill_per_sec)
+        } else {
+            Duration::MAX
+        };
+        Err(ServiceError::RateLimited { retry_after_ms: wait.as_millis().try_into().unwrap_or(u64::MAX) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn peer(last: u8) -> RateKey {
+        RateKey::Peer(IpAddr::V4(Ipv4Addr::new(10, 0, 0, last)))
+    }
+
+    fn retry_after(result: Result<(), ServiceError>) -> u64 {
+        match result {
+            Err(ServiceError::RateLimited { retry_after_ms }) => retry_after_ms,
+            other => panic!("se esperaba RateLimited y llegó {other:?}"),
+        }
+    }
+
+    #[test]
+    fn burst_runs_out() {
+        let limiter = RateLimiter::new(3, 60);
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.check_at(peer(1), now).is_ok());
+        }
+        // un token por segundo: el siguiente llega en 1 s
+        assert_eq!(retry_after(limiter.check_at(peer(1), now)), 1000);
+    }
+
+    #[test]
+    fn refills_after_the_interval() {
+        let limiter = RateLimiter::new(1, 60);
+        let now = Instant::now();
+        assert!(limiter.check_at(peer(1), now).is_ok());
+        assert_eq!(retry_after(limiter.check_at(peer(1), now + Duration::from_millis(400))), 600);
+        assert!(limiter.check_at(peer(1), now + Duration::from_secs(1)).is_ok());
+        assert!(limiter.check_at(peer(1), now + Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn refill_stops_at_the_burst() {
+        let limiter = RateLimiter::new(2, 60);
+        let now = Instant::now();
+        assert!(limiter.check_at(peer(1), now).is_ok());
+        let later = now + Duration::from_secs(3600);
+        assert!(limiter.check_at(peer(1), later).is_ok());
+        assert!(limiter.check_at(peer(1), later).is_ok());
+        assert!(limiter.check_at(peer(1), later).is_err());
+    }
+
+    #[test]
+    fn no_refill_never_lets_more_through() {
+        let limiter = RateLimiter::new(1, 0);
+        let now = Instant::now();
+        assert!(limiter.check_at(peer(1), now).is_ok());
+        assert_eq!(retry_after(limiter.check_at(peer(1), now + Duration::from_secs(3600))), u64::MAX);
+    }
+
+    #[test]
+    fn each_key_has_its_own_bucket() {
+        let limiter = RateLimiter::new(1, 60);
+        let now = Instant::now();
+        assert!(limiter.check_at(peer(1), now).is_ok());
+        assert!(limiter.check_at(peer(1), now).is_err());
+        assert!(limiter.check_at(peer(2), now).is_ok());
+        assert!(limiter.check_at(RateKey::User(1), now).is_ok());
+        assert!(limiter.check_at(RateKey::User(2), now).is_ok());
+        assert!(limiter.check_at(RateKey::User(1), now).is_err());
+    }
+
+    #[test]
+    fn idle_full_buckets_are_dropped() {
+        let limiter = RateLimiter::new(2, 60);
+        let now = Instant::now();
+        for user in 0..=MAX_IDLE_BUCKETS as UserId {
+            assert!(limiter.check_at(RateKey::User(user), now).is_ok());
+        }
+        // uno sigue sin tokens de sobra; los demás se han llenado al cabo de un segundo
+        assert!(limiter.check_at(RateKey::User(0), now).is_ok());
+        assert!(limiter.check_at(peer(1), now + Duration::from_secs(1)).is_ok());
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), 2);
+        assert!(buckets.contains_key(&RateKey::User(0)));
+    }
+}
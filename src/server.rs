@@ -3,36 +3,543 @@ use rand::{
     distr::{Distribution, Uniform},
     rng,
 };
-use service::{World};
+use service::{ServiceError, World};
+use service::account::{Session, UserId};
+use service::event::Event;
+use service::replay::Replay;
+use service::game::{Game, GameId, SolveRecord, Streak};
+use service::race::{RaceStatus, RoomId};
 use std::{
-    net::{IpAddr, Ipv6Addr, SocketAddr},
-    time::Duration,
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
 };
 use tarpc::{
     context,
-    server::{self, Channel, incoming::Incoming},
+    server::{self, Channel},
     tokio_serde::formats::Json,
 };
-use service::sudoku::{Sudoku, SudokuSize, SudokuState};
-use tokio::time;
+use service::sudoku::{Cancel, Difficulty, Sudoku, SudokuSize, SudokuState};
+use tokio::{sync::oneshot, time};
+use subtle::ConstantTimeEq;
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+use crate::accounts::Accounts;
+use crate::config::ServerConfig;
+use crate::daily::{self, DailyPuzzles};
+use crate::games::Games;
+use crate::puzzle_pool::PuzzlePool;
+use crate::races::Races;
+use crate::rate_limit::{RateKey, RateLimiter};
+use crate::storage::{MoveKind, SqliteStorage, Storage};
+use crate::workers::BlockingPool;
+
+/// Cloneable handle that stops a running server. Triggering it stops accepting connections and
+/// lets the requests already in flight finish, up to the configured drain timeout.
+#[derive(Clone, Default)]
+pub struct ShutdownHandle(CancellationToken);
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        self.0.cancel();
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.0.is_cancelled()
+    }
+
+    pub async fn wait(&self) {
+        self.0.cancelled().await
+    }
+
+    /// A [`Cancel`] that fires once the server shuts down, for generation no request waits on.
+    pub fn cancel(&self) -> Cancel {
+        let cancel = Cancel::never();
+        let linked = cancel.clone();
+        let handle = self.clone();
+        tokio::spawn(async move {
+            handle.wait().await;
+            linked.cancel();
+        });
+        cancel
+    }
+
+    /// Triggers the shutdown on SIGINT or SIGTERM.
+    pub fn on_signals(&self) {
+        let handle = self.clone();
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            tracing::info!(target: "server", "Señal recibida, apagando");
+            handle.shutdown();
+        });
+    }
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let Ok(mut term) = signal(SignalKind::terminate()) else {
+        let _ = tokio::signal::ctrl_c().await;
+        return;
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = term.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Blocking threads that may use the database at once, apart from those generating puzzles.
+const STORAGE_WORKERS: usize = 4;
+
+/// How long a rejected connection is kept open so its first requests get the error back.
+const REJECTED_GRACE: Duration = Duration::from_secs(2);
+
+/// State shared by every connection.
+struct ServerState {
+    shutdown: ShutdownHandle,
+    admin_token: Option<String>,
+    rate_limiter: RateLimiter,
+    generators: BlockingPool,
+    puzzles: Arc<PuzzlePool>,
+    games: Games,
+    accounts: Accounts,
+    daily: DailyPuzzles,
+    races: Races,
+}
+
+/// Counts open connections, in total and per client address.
+struct Connections {
+    max_total: usize,
+    max_per_ip: usize,
+    open: Mutex<HashMap<IpAddr, usize>>,
+}
+
+/// Keeps a connection counted in [`Connections`] until dropped.
+struct ConnectionPermit {
+    connections: Arc<Connections>,
+    ip: IpAddr,
+}
+
+impl Connections {
+    fn admit(self: &Arc<Self>, ip: IpAddr) -> Result<ConnectionPermit, ServiceError> {
+        let mut open = self.open.lock().unwrap();
+
+        if open.values().sum::<usize>() >= self.max_total {
+            return Err(ServiceError::ServerFull { limit: self.max_total });
+        }
+
+        let count = open.get(&ip).copied().unwrap_or(0);
+        if count >= self.max_per_ip {
+            return Err(ServiceError::TooManyConnections { limit: self.max_per_ip });
+        }
+
+        open.insert(ip, count + 1);
+        Ok(ConnectionPermit { connections: self.clone(), ip })
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut open = self.connections.open.lock().unwrap();
+        if let Some(count) = open.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                open.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// Cancels the wrapped token when the request future holding it is dropped.
+struct CancelOnDrop(Cancel);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
 
 #[derive(Clone)]
-struct HelloServer(SocketAddr);
+struct HelloServer {
+    peer: SocketAddr,
+    state: Arc<ServerState>,
+    /// Set when the connection went over a limit; every request gets this error back.
+    rejection: Option<ServiceError>,
+    /// Player logged in on this connection, shared by all its requests.
+    session: Arc<Mutex<Option<Session>>>,
+    /// Games this connection watches as a spectator.
+    spectating: Arc<Mutex<HashSet<GameId>>>,
+}
+
+impl HelloServer {
+    fn admitted(&self) -> Result<(), ServiceError> {
+        match &self.rejection {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn user(&self) -> Option<UserId> {
+        self.session.lock().unwrap().as_ref().map(|s| s.user_id)
+    }
+
+    fn session(&self) -> Result<Session, ServiceError> {
+        self.session.lock().unwrap().clone().ok_or(ServiceError::NotLoggedIn)
+    }
+
+    /// Shared by `undo` and `redo`: steps the caller's history in `game_id` one way or the other.
+    async fn undo_move(&self, ctx: &context::Context, game_id: GameId, kind: MoveKind) -> Result<Game, ServiceError> {
+        self.admitted()?;
+        within_deadline(ctx)?;
+        if self.spectating.lock().unwrap().contains(&game_id) {
+            return Err(ServiceError::Spectating(game_id));
+        }
+        let player = self.session().ok();
+        let (placement, game) = self.state.games.undo(game_id, player.as_ref(), kind).await?;
+        self.state.races.on_move(game_id, &placement);
+        Ok(game)
+    }
+
+    /// Charges a request to the logged-in player, or to the client's address if anonymous.
+    fn rate_limit(&self, key: RateKey) -> Result<(), ServiceError> {
+        self.state.rate_limiter.check(key).inspect_err(|err| {
+            tracing::warn!(target: "server", "{} limitado: {err}", self.peer);
+        })
+    }
+
+    /// Ties this connection to `session` from now on.
+    fn log_in(&self, session: Session) -> Session {
+        tracing::info!(target: "server", "{} identificado como {}", self.peer, session.username);
+        *self.session.lock().unwrap() = Some(session.clone());
+        session
+    }
+
+    /// A fresh puzzle for `ctx`, from the pool if it has one, charged to the client's rate limit.
+    async fn new_puzzle(&self, ctx: &context::Context, size: SudokuSize, difficulty: Difficulty) -> Result<Sudoku, ServiceError> {
+        within_deadline(ctx)?;
+        self.rate_limit(self.user().map_or(RateKey::Peer(self.peer.ip()), RateKey::User))?;
+
+        if let Some(puzzle) = self.state.puzzles.take(size, difficulty) {
+            tracing::debug!(target: "server", "Sudoku {size:?} {difficulty:?} servido desde el pool");
+            return Ok(puzzle);
+        }
+
+        // tarpc suelta este future si el cliente cancela o vence el plazo; el guard avisa entonces
+        // al hilo de generación para que no siga trabajando para nadie
+        let cancel = Cancel::with_deadline(ctx.deadline);
+        let _guard = CancelOnDrop(cancel.clone());
+
+        let job = cancel.clone();
+        let result = self.state.generators
+            .run(move || Sudoku::generate(size, difficulty, &job))
+            .await;
+        match result {
+            Err(_) if cancel.is_cancelled() => {
+                tracing::warn!(target: "server", "Generación para {} abortada por el plazo", self.peer);
+                Err(ServiceError::Timeout)
+            }
+            result => result.map_err(ServiceError::Generation),
+        }
+    }
+}
+
+/// Fails with [`ServiceError::Timeout`] if the client already stopped waiting for `ctx`.
+fn within_deadline(ctx: &context::Context) -> Result<(), ServiceError> {
+    if Instant::now() >= ctx.deadline {
+        return Err(ServiceError::Timeout);
+    }
+    Ok(())
+}
 
 impl World for HelloServer {
-    async fn hello(self, _: context::Context, name: String) -> String {
+    async fn hello(self, _: context::Context, name: String) -> Result<String, ServiceError> {
+        self.admitted()?;
         let sleep_time =
             Duration::from_millis(Uniform::new_inclusive(1, 10).unwrap().sample(&mut rng()));
         time::sleep(sleep_time).await;
-        format!("Hello, {name}! You are connected from {}", self.0)
+        Ok(format!("Hello, {name}! You are connected from {}", self.peer))
+    }
+
+    async fn sudoku(self, ctx: context::Context, size: SudokuSize, difficulty: Difficulty) -> Result<Sudoku, ServiceError> {
+        self.admitted()?;
+        self.new_puzzle(&ctx, size, difficulty).await
+    }
+
+    async fn is_solved(self, ctx: context::Context, sudoku: Sudoku) -> Result<SudokuState, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        Ok(sudoku.clone().check_user_board(&sudoku.board, sudoku.sudoku_size))
+    }
+
+    async fn start(self, ctx: context::Context, size: SudokuSize, difficulty: Difficulty) -> Result<Game, ServiceError> {
+        self.admitted()?;
+        let puzzle = self.new_puzzle(&ctx, size, difficulty).await?;
+        let game = self.state.games.start(puzzle, self.user()).await?;
+        tracing::info!(target: "server", "Partida {} empezada por {}", game.id, self.peer);
+        Ok(game)
+    }
+
+    async fn resume(self, ctx: context::Context, game_id: GameId) -> Result<Game, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        self.state.games.resume(game_id, self.user()).await
+    }
+
+    async fn place(self, ctx: context::Context, game_id: GameId, row: u8, col: u8, value: u8) -> Result<Game, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        if self.spectating.lock().unwrap().contains(&game_id) {
+            return Err(ServiceError::Spectating(game_id));
+        }
+        let player = self.session().ok();
+        let (placement, game) = self.state.games.place(game_id, player.as_ref(), row, col, value).await?;
+        self.state.races.on_move(game_id, &placement);
+        Ok(game)
     }
 
-    async fn sudoku(self, _: context::Context, size: SudokuSize) -> Result<Sudoku, String> {
-        Sudoku::generate_sudoku(size).await
+    async fn undo(self, ctx: context::Context, game_id: GameId) -> Result<Game, ServiceError> {
+        self.undo_move(&ctx, game_id, MoveKind::Undo).await
     }
 
-    async fn is_solved(self, _: context::Context, sudoku: Sudoku) -> SudokuState {
-        sudoku.clone().check_user_board(&sudoku.board, sudoku.sudoku_size)
+    async fn redo(self, ctx: context::Context, game_id: GameId) -> Result<Game, ServiceError> {
+        self.undo_move(&ctx, game_id, MoveKind::Redo).await
+    }
+
+    async fn pause(self, ctx: context::Context, game_id: GameId, paused: bool) -> Result<Game, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        if self.spectating.lock().unwrap().contains(&game_id) {
+            return Err(ServiceError::Spectating(game_id));
+        }
+        self.state.games.pause(game_id, self.user(), paused).await
+    }
+
+    async fn note(self, ctx: context::Context, game_id: GameId, row: u8, col: u8, value: u8) -> Result<Game, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        if self.spectating.lock().unwrap().contains(&game_id) {
+            return Err(ServiceError::Spectating(game_id));
+        }
+        let player = self.session().ok();
+        self.state.games.note(game_id, player.as_ref(), row, col, value).await
+    }
+
+    async fn leaderboard(self, ctx: context::Context, size: SudokuSize, difficulty: Difficulty, limit: u32) -> Result<Vec<SolveRecord>, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        self.state.games.leaderboard(size, difficulty, limit).await
+    }
+
+    async fn personal_best(self, ctx: context::Context, size: SudokuSize, difficulty: Difficulty) -> Result<Option<SolveRecord>, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        let user = self.user().ok_or(ServiceError::NotLoggedIn)?;
+        self.state.games.personal_best(user, size, difficulty).await
+    }
+
+    async fn daily(self, ctx: context::Context, size: SudokuSize) -> Result<Game, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        self.rate_limit(self.user().map_or(RateKey::Peer(self.peer.ip()), RateKey::User))?;
+
+        let day = daily::today();
+        let cancel = Cancel::with_deadline(ctx.deadline);
+        let _guard = CancelOnDrop(cancel.clone());
+        let puzzle = match self.state.daily.get(day, size, &self.state.generators, cancel.clone()).await {
+            Err(_) if cancel.is_cancelled() => return Err(ServiceError::Timeout),
+            result => result.map_err(ServiceError::Generation)?,
+        };
+        self.state.games.start_daily(puzzle, self.user(), day).await
+    }
+
+    async fn daily_leaderboard(self, ctx: context::Context, size: SudokuSize, limit: u32) -> Result<Vec<SolveRecord>, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        self.state.games.daily_leaderboard(daily::today(), size, limit).await
+    }
+
+    async fn streak(self, ctx: context::Context) -> Result<Streak, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        let user = self.user().ok_or(ServiceError::NotLoggedIn)?;
+        self.state.games.streak(user, daily::today()).await
+    }
+
+    async fn start_coop(self, ctx: context::Context, size: SudokuSize, difficulty: Difficulty) -> Result<Game, ServiceError> {
+        self.admitted()?;
+        let session = self.session()?;
+        let puzzle = self.new_puzzle(&ctx, size, difficulty).await?;
+        let game = self.state.games.start_coop(puzzle, &session).await?;
+        tracing::info!(target: "server", "Partida cooperativa {} empezada por {}", game.id, session.username);
+        Ok(game)
+    }
+
+    async fn join_coop(self, ctx: context::Context, game_id: GameId) -> Result<Game, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        if self.spectating.lock().unwrap().contains(&game_id) {
+            return Err(ServiceError::Spectating(game_id));
+        }
+        self.state.games.join_coop(game_id, &self.session()?).await
+    }
+
+    async fn spectate(self, ctx: context::Context, game_id: GameId) -> Result<Game, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        let game = self.state.games.spectate(game_id).await?;
+        self.spectating.lock().unwrap().insert(game_id);
+        tracing::info!(target: "server", "{} mira la partida {game_id}", self.peer);
+        Ok(game)
+    }
+
+    async fn replay(self, ctx: context::Context, game_id: GameId) -> Result<Replay, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        self.state.games.replay(game_id).await
+    }
+
+    async fn next_events(self, ctx: context::Context, game_id: GameId, since: u64) -> Result<Vec<Event>, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        let spectator = self.spectating.lock().unwrap().contains(&game_id);
+        tokio::select! {
+            events = self.state.games.next_events(game_id, self.user(), spectator, since, ctx.deadline) => events,
+            _ = self.state.shutdown.wait() => Err(ServiceError::ShuttingDown),
+        }
+    }
+
+    async fn chat(self, ctx: context::Context, game_id: GameId, text: String) -> Result<(), ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        self.state.games.chat(game_id, &self.session()?, text).await
+    }
+
+    async fn create_room(self, ctx: context::Context, size: SudokuSize, difficulty: Difficulty) -> Result<RaceStatus, ServiceError> {
+        self.admitted()?;
+        let session = self.session()?;
+        let puzzle = self.new_puzzle(&ctx, size, difficulty).await?;
+        Ok(self.state.races.create(&session, puzzle))
+    }
+
+    async fn join_room(self, ctx: context::Context, room: RoomId) -> Result<RaceStatus, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        self.state.races.join(room, &self.session()?)
+    }
+
+    async fn start_race(self, ctx: context::Context, room: RoomId) -> Result<RaceStatus, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        self.state.races.start(room, self.session()?.user_id, &self.state.games).await
+    }
+
+    async fn race_status(self, ctx: context::Context, room: RoomId, since: u64) -> Result<RaceStatus, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        tokio::select! {
+            status = self.state.races.wait(room, since, ctx.deadline) => status,
+            _ = self.state.shutdown.wait() => Err(ServiceError::ShuttingDown),
+        }
+    }
+
+    async fn register(self, ctx: context::Context, username: String, password: String) -> Result<Session, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        self.rate_limit(RateKey::Peer(self.peer.ip()))?;
+        let session = self.state.accounts.register(username, password).await?;
+        Ok(self.log_in(session))
+    }
+
+    async fn login(self, ctx: context::Context, username: String, password: String) -> Result<Session, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        self.rate_limit(RateKey::Peer(self.peer.ip()))?;
+        match self.state.accounts.login(username, password).await {
+            Ok(session) => Ok(self.log_in(session)),
+            Err(err) => {
+                tracing::warn!(target: "server", "Login fallido desde {}", self.peer);
+                Err(err)
+            }
+        }
+    }
+
+    async fn authenticate(self, ctx: context::Context, token: String) -> Result<Session, ServiceError> {
+        self.admitted()?;
+        within_deadline(&ctx)?;
+        let session = self.state.accounts.authenticate(token).await?;
+        Ok(self.log_in(session))
+    }
+
+    async fn shutdown(self, _: context::Context, admin_token: String) -> Result<(), ServiceError> {
+        self.admitted()?;
+        match &self.state.admin_token {
+            Some(token) if bool::from(token.as_bytes().ct_eq(admin_token.as_bytes())) => {
+                tracing::info!(target: "server", "Apagado pedido por {}", self.peer);
+                self.state.shutdown.shutdown();
+                Ok(())
+            }
+            _ => {
+                tracing::warn!(target: "server", "Apagado rechazado para {}", self.peer);
+                Err(ServiceError::Unauthorized)
+            }
+        }
+    }
+}
+
+/// Transport that reports end of stream once shutdown starts. tarpc then treats the read half as
+/// closed: it stops taking new requests but keeps the channel open until in-flight ones answer.
+struct Draining<T> {
+    inner: T,
+    shutdown: Option<Pin<Box<WaitForCancellationFutureOwned>>>,
+}
+
+impl<T> Draining<T> {
+    fn new(inner: T, shutdown: &ShutdownHandle) -> Self {
+        Draining { inner, shutdown: Some(Box::pin(shutdown.0.clone().cancelled_owned())) }
+    }
+}
+
+impl<T: Stream + Unpin> Stream for Draining<T> {
+    type Item = T::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T::Item>> {
+        let closed = match &mut self.shutdown {
+            Some(shutdown) => shutdown.as_mut().poll(cx).is_ready(),
+            None => true,
+        };
+        if closed {
+            self.shutdown = None;
+            return Poll::Ready(None);
+        }
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
+
+impl<T: Sink<I> + Unpin, I> Sink<I> for Draining<T> {
+    type Error = T::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: I) -> Result<(), Self::Error> {
+        Pin::new(&mut self.inner).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner).poll_close(cx)
     }
 }
 
@@ -40,28 +547,101 @@ async fn spawn(fut: impl Future<Output = ()> + Send + 'static) {
     tokio::spawn(fut);
 }
 
-pub async fn run_server(port: u16) -> anyhow::Result<()> {
-    let server_addr = (IpAddr::V6(Ipv6Addr::LOCALHOST), port);
+/// Binds the configured address and serves `World` until `shutdown` is triggered. Once the
+/// listener is up, the address it actually bound to (useful with port 0) is sent through `ready`.
+pub async fn run_server(
+    config: ServerConfig,
+    ready: oneshot::Sender<SocketAddr>,
+    shutdown: ShutdownHandle,
+) -> anyhow::Result<()> {
+    let storage = SqliteStorage::open(&config.database)
+        .map_err(|e| anyhow::anyhow!("no se pudo abrir {}: {e}", config.database.display()))?;
+
     let mut listener =
-        tarpc::serde_transport::tcp::listen(&server_addr, Json::default).await?;
+        tarpc::serde_transport::tcp::listen(&config.addr(), Json::default).await?;
 
     listener.config_mut().max_frame_length(usize::MAX);
 
     println!("Servidor escuchando en {}", listener.local_addr());
+    let _ = ready.send(listener.local_addr());
     tracing::info!(target: "server", "Server Up");
 
-    listener
+    let storage: Arc<dyn Storage> = Arc::new(storage);
+    let generators = BlockingPool::new(config.generation_workers);
+    let io = BlockingPool::new(STORAGE_WORKERS);
+    let state = Arc::new(ServerState {
+        shutdown: shutdown.clone(),
+        admin_token: config.admin_token.clone(),
+        rate_limiter: RateLimiter::new(config.rate_limit_burst, config.rate_limit_per_minute),
+        generators: generators.clone(),
+        puzzles: PuzzlePool::new(config.puzzle_pool_depth, config.puzzle_pool_refill_per_minute),
+        games: Games::new(storage.clone(), io.clone()),
+        accounts: Accounts::new(storage, io, generators),
+        daily: DailyPuzzles::default(),
+        races: Races::default(),
+    });
+
+    tokio::spawn(state.puzzles.clone().refill(state.generators.clone(), shutdown.clone()));
+
+    let connections = Arc::new(Connections {
+        max_total: config.max_connections,
+        max_per_ip: config.max_connections_per_ip,
+        open: Mutex::new(HashMap::new()),
+    });
+
+    let serving = listener
+        .take_until(shutdown.0.clone().cancelled_owned())
         .filter_map(|r| future::ready(r.ok()))
-        .map(server::BaseChannel::with_defaults)
-        .max_channels_per_key(1, |t| t.transport().peer_addr().unwrap().ip())
-        .map(|channel| {
-            let server = HelloServer(channel.transport().peer_addr().unwrap());
-            channel.execute(server.serve()).for_each(spawn)
-        })
-        .buffer_unordered(10)
-        .for_each(|_| async {})
-        .await;
+        // un cliente que corta justo después de conectar ya no tiene dirección: se le ignora
+        .filter_map(|transport| future::ready(match transport.peer_addr() {
+            Ok(peer) => Some((transport, peer)),
+            Err(err) => {
+                tracing::warn!(target: "server", "Conexión descartada: {err}");
+                None
+            }
+        }))
+        .for_each_concurrent(None, |(transport, peer)| {
+            let admission = connections.admit(peer.ip());
+
+            let channel = server::BaseChannel::with_defaults(Draining::new(transport, &shutdown))
+                .max_concurrent_requests(config.max_requests_per_channel);
+            let server = HelloServer {
+                peer,
+                state: state.clone(),
+                rejection: admission.as_ref().err().cloned(),
+                session: Arc::default(),
+                spectating: Arc::default(),
+            };
+
+            async move {
+                match admission {
+                    Ok(_permit) => channel.execute(server.serve()).for_each(spawn).await,
+                    Err(err) => {
+                        tracing::warn!(target: "server", "Conexión de {peer} rechazada: {err}");
+                        let _ = time::timeout(REJECTED_GRACE, channel.execute(server.serve()).for_each(spawn)).await;
+                    }
+                }
+            }
+        });
+    tokio::pin!(serving);
 
+    tokio::select! {
+        _ = &mut serving => {}
+        _ = shutdown.wait() => {
+            tracing::info!(target: "server", "Dejando de aceptar conexiones, esperando peticiones en curso");
+            if time::timeout(config.drain_timeout, &mut serving).await.is_err() {
+                tracing::warn!(
+                    target: "server",
+                    "Plazo de {:?} agotado, se cierran las conexiones pendientes",
+                    config.drain_timeout
+                );
+            }
+        }
+    }
+
+    if let Err(err) = state.games.flush() {
+        tracing::error!(target: "server", "No se pudo volcar la base de datos: {err:#}");
+    }
+    tracing::info!(target: "server", "Server Down");
     Ok(())
 }
-
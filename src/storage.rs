@@ -0,0 +1,526 @@
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use clap::ValueEnum;
+use rusqlite::{params, Connection, OptionalExtension};
+use service::account::UserId;
+use service::ServiceError;
+use service::game::{GameId, Move, SolveRecord};
+use service::sudoku::{format_board, parse_board, Difficulty, Sudoku, SudokuSize, SudokuState};
+
+pub type PuzzleId = u64;
+
+/// Game ids are drawn at random below this, so nobody can play an anonymous game by guessing its
+/// id. 48 bits keep them short enough to type.
+const MAX_GAME_ID: GameId = 1 << 48;
+
+/// Milliseconds since the Unix epoch.
+pub fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Logs a storage failure and reports it to the client.
+pub fn storage_error(err: anyhow::Error) -> ServiceError {
+    tracing::error!(target: "server", "Error de almacenamiento: {err:#}");
+    ServiceError::Storage(err.to_string())
+}
+
+/// A game as persisted, solution included.
+#[derive(Debug, Clone)]
+pub struct StoredGame {
+    /// The generated puzzle; `board` holds the givens and `solved` the solution.
+    pub puzzle: Sudoku,
+    pub board: Vec<Vec<u8>>,
+    /// The player who started it; `None` for anonymous games.
+    pub user: Option<UserId>,
+    pub daily: Option<u64>,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    /// Time spent paused in earlier pauses.
+    pub paused_ms: u64,
+    /// When the current pause began, if the game is paused.
+    pub paused_at: Option<u64>,
+    /// See [`Game::notes`](service::game::Game::notes).
+    pub notes: Vec<Vec<u16>>,
+    /// Players of a cooperative game, in the order they joined; empty for any other game.
+    pub players: Vec<(UserId, String)>,
+}
+
+/// Why a move was made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveKind {
+    Place,
+    /// Put back the value a `Place` by the same player replaced.
+    Undo,
+    /// Wrote again the value of a `Place` that was undone.
+    Redo,
+}
+
+impl MoveKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            MoveKind::Place => "place",
+            MoveKind::Undo => "undo",
+            MoveKind::Redo => "redo",
+        }
+    }
+
+    fn parse(kind: &str) -> anyhow::Result<Self> {
+        match kind {
+            "place" => Ok(MoveKind::Place),
+            "undo" => Ok(MoveKind::Undo),
+            "redo" => Ok(MoveKind::Redo),
+            _ => anyhow::bail!("tipo de jugada desconocido: {kind}"),
+        }
+    }
+}
+
+/// A move as persisted, with the player who made it.
+#[derive(Debug, Clone)]
+pub struct StoredMove {
+    pub mv: Move,
+    /// `None` for anonymous players.
+    pub user: Option<(UserId, String)>,
+    pub kind: MoveKind,
+}
+
+/// Where the server keeps puzzles, games, their moves and completion records.
+pub trait Storage: Send + Sync {
+    fn save_puzzle(&self, puzzle: &Sudoku) -> anyhow::Result<PuzzleId>;
+    /// `daily` is the UTC day if the puzzle is that day's daily puzzle. The id is random.
+    fn create_game(&self, puzzle_id: PuzzleId, user: Option<UserId>, daily: Option<u64>, board: &[Vec<u8>], started_at: u64) -> anyhow::Result<GameId>;
+    fn load_game(&self, id: GameId) -> anyhow::Result<Option<StoredGame>>;
+    /// Appends `mv`, made by `user`, to the game's history and saves the board it produced.
+    fn record_move(&self, id: GameId, mv: &Move, user: Option<UserId>, kind: MoveKind, board: &[Vec<u8>]) -> anyhow::Result<()>;
+    /// Every move of the game in the order they were made, with the player who made it.
+    fn moves(&self, id: GameId) -> anyhow::Result<Vec<StoredMove>>;
+    /// Adds `user` to the players of a cooperative game.
+    fn add_player(&self, id: GameId, user: UserId, joined_at: u64) -> anyhow::Result<()>;
+    /// Records that the game is paused since `paused_at`, or running if `None`, after `paused_ms`
+    /// spent in earlier pauses.
+    fn set_paused(&self, id: GameId, paused_at: Option<u64>, paused_ms: u64) -> anyhow::Result<()>;
+    /// Stores the notes of each `(row, col, marks)` cell, replacing what it had.
+    fn save_notes(&self, id: GameId, cells: &[(u8, u8, u16)]) -> anyhow::Result<()>;
+    /// Marks the game finished and stores how long it took, pauses left out.
+    fn finish_game(&self, id: GameId, finished_at: u64) -> anyhow::Result<()>;
+    /// Creates a user, or returns `None` if the name is taken.
+    fn create_user(&self, username: &str, password_hash: &str) -> anyhow::Result<Option<UserId>>;
+    /// Id and password hash of `username`.
+    fn find_user(&self, username: &str) -> anyhow::Result<Option<(UserId, String)>>;
+    fn create_session(&self, token: &str, user: UserId, expires_at: u64) -> anyhow::Result<()>;
+    /// The user a session belongs to, if the token exists and has not expired at `now`.
+    fn session_user(&self, token: &str, now: u64) -> anyhow::Result<Option<(UserId, String)>>;
+    /// Best time per player for `size` and `difficulty`, fastest first.
+    fn leaderboard(&self, size: SudokuSize, difficulty: Difficulty, limit: u32) -> anyhow::Result<Vec<SolveRecord>>;
+    fn personal_best(&self, user: UserId, size: SudokuSize, difficulty: Difficulty) -> anyhow::Result<Option<SolveRecord>>;
+    /// The game `user` started on `day`'s daily puzzle of `size`, if any.
+    fn daily_game(&self, user: UserId, day: u64, size: SudokuSize) -> anyhow::Result<Option<GameId>>;
+    /// Fastest solves of `day`'s daily puzzle of `size`.
+    fn daily_leaderboard(&self, day: u64, size: SudokuSize, limit: u32) -> anyhow::Result<Vec<SolveRecord>>;
+    /// Days on which `user` solved a daily puzzle, most recent first.
+    fn daily_days_solved(&self, user: UserId) -> anyhow::Result<Vec<u64>>;
+    /// Makes sure everything written so far is durable.
+    fn flush(&self) -> anyhow::Result<()>;
+}
+
+/// [`Storage`] on a SQLite file.
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+}
+
+/// Schema changes, applied in order. `PRAGMA user_version` records how many have run.
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE IF NOT EXISTS puzzles (
+        id          INTEGER PRIMARY KEY,
+        size        TEXT NOT NULL,
+        difficulty  TEXT NOT NULL,
+        board       TEXT NOT NULL,
+        solution    TEXT NOT NULL,
+        created_at  INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS games (
+        id           INTEGER PRIMARY KEY,
+        puzzle_id    INTEGER NOT NULL REFERENCES puzzles(id),
+        board        TEXT NOT NULL,
+        started_at   INTEGER NOT NULL,
+        finished_at  INTEGER
+    );
+    CREATE TABLE IF NOT EXISTS moves (
+        game_id  INTEGER NOT NULL REFERENCES games(id),
+        seq      INTEGER NOT NULL,
+        row      INTEGER NOT NULL,
+        col      INTEGER NOT NULL,
+        value    INTEGER NOT NULL,
+        at       INTEGER NOT NULL,
+        PRIMARY KEY (game_id, seq)
+    );
+    CREATE TABLE IF NOT EXISTS completions (
+        game_id       INTEGER PRIMARY KEY REFERENCES games(id),
+        size          TEXT NOT NULL,
+        difficulty    TEXT NOT NULL,
+        millis        INTEGER NOT NULL,
+        completed_at  INTEGER NOT NULL
+    );
+", "
+    CREATE TABLE users (
+        id             INTEGER PRIMARY KEY,
+        username       TEXT NOT NULL UNIQUE,
+        password_hash  TEXT NOT NULL,
+        created_at     INTEGER NOT NULL
+    );
+    CREATE TABLE sessions (
+        token       TEXT PRIMARY KEY,
+        user_id     INTEGER NOT NULL REFERENCES users(id),
+        expires_at  INTEGER NOT NULL
+    );
+    ALTER TABLE games ADD COLUMN user_id INTEGER REFERENCES users(id);
+", "
+    CREATE INDEX completions_by_time ON completions (size, difficulty, millis);
+", "
+    ALTER TABLE games ADD COLUMN daily INTEGER;
+    CREATE INDEX games_by_daily ON games (daily, user_id);
+", "
+    CREATE TABLE game_players (
+        game_id    INTEGER NOT NULL REFERENCES games(id),
+        user_id    INTEGER NOT NULL REFERENCES users(id),
+        joined_at  INTEGER NOT NULL,
+        PRIMARY KEY (game_id, user_id)
+    );
+    ALTER TABLE moves ADD COLUMN user_id INTEGER REFERENCES users(id);
+", "
+    ALTER TABLE moves ADD COLUMN kind TEXT NOT NULL DEFAULT 'place';
+", "
+    ALTER TABLE games ADD COLUMN paused_ms INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE games ADD COLUMN paused_at INTEGER;
+", "
+    CREATE TABLE notes (
+        game_id  INTEGER NOT NULL REFERENCES games(id),
+        row      INTEGER NOT NULL,
+        col      INTEGER NOT NULL,
+        marks    INTEGER NOT NULL,
+        PRIMARY KEY (game_id, row, col)
+    );
+"];
+
+fn value_name<T: ValueEnum>(value: &T) -> String {
+    value.to_possible_value().expect("sin variantes ocultas").get_name().to_string()
+}
+
+fn from_value_name<T: ValueEnum>(name: &str) -> anyhow::Result<T> {
+    T::from_str(name, true).map_err(anyhow::Error::msg)
+}
+
+fn solve_record(row: &rusqlite::Row) -> rusqlite::Result<SolveRecord> {
+    Ok(SolveRecord { username: row.get(0)?, millis: row.get(1)?, completed_at: row.get(2)? })
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut conn = Connection::open(path)?;
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;")?;
+
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+        }
+
+        Ok(SqliteStorage { conn: Mutex::new(conn) })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn save_puzzle(&self, puzzle: &Sudoku) -> anyhow::Result<PuzzleId> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO puzzles (size, difficulty, board, solution, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                value_name(&puzzle.sudoku_size),
+                value_name(&puzzle.difficulty),
+                format_board(&puzzle.board),
+                format_board(&puzzle.solved),
+                now_ms(),
+            ],
+        )?;
+        Ok(conn.last_insert_rowid() as PuzzleId)
+    }
+
+    fn create_game(&self, puzzle_id: PuzzleId, user: Option<UserId>, daily: Option<u64>, board: &[Vec<u8>], started_at: u64) -> anyhow::Result<GameId> {
+        let conn = self.conn.lock().unwrap();
+        loop {
+            let id = rand::random_range(1..MAX_GAME_ID);
+            let inserted = conn.execute(
+                "INSERT INTO games (id, puzzle_id, user_id, daily, board, started_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![id, puzzle_id, user, daily, format_board(board), started_at],
+            );
+            match inserted {
+                Ok(_) => return Ok(id),
+                // id ya usado: se prueba con otro
+                Err(rusqlite::Error::SqliteFailure(err, _))
+                    if err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_PRIMARYKEY => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    fn load_game(&self, id: GameId) -> anyhow::Result<Option<StoredGame>> {
+        let conn = self.conn.lock().unwrap();
+
+        let row = conn.query_row(
+            "SELECT g.board, g.user_id, g.daily, g.started_at, g.finished_at,
+                    p.size, p.difficulty, p.board, p.solution, g.paused_ms, g.paused_at
+             FROM games g JOIN puzzles p ON p.id = g.puzzle_id
+             WHERE g.id = ?1",
+            params![id],
+            |row| Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<UserId>>(1)?,
+                row.get::<_, Option<u64>>(2)?,
+                row.get::<_, u64>(3)?,
+                row.get::<_, Option<u64>>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, String>(6)?,
+                row.get::<_, String>(7)?,
+                row.get::<_, String>(8)?,
+                row.get::<_, u64>(9)?,
+                row.get::<_, Option<u64>>(10)?,
+            )),
+        ).optional()?;
+
+        let Some((board, user, daily, started_at, finished_at, size, difficulty, givens, solution, paused_ms, paused_at)) = row else {
+            return Ok(None);
+        };
+
+        let mut stmt = conn.prepare(
+            "SELECT u.id, u.username FROM game_players gp JOIN users u ON u.id = gp.user_id
+             WHERE gp.game_id = ?1
+             ORDER BY gp.joined_at, gp.rowid",
+        )?;
+        let players = stmt.query_map(params![id], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<Vec<_>, _>>()?;
+
+        let board = parse_board(&board).map_err(anyhow::Error::msg)?;
+        let mut notes = vec![vec![0; board.len()]; board.len()];
+        let mut stmt = conn.prepare("SELECT row, col, marks FROM notes WHERE game_id = ?1")?;
+        for cell in stmt.query_map(params![id], |row| Ok((row.get::<_, usize>(0)?, row.get::<_, usize>(1)?, row.get(2)?)))? {
+            let (r, c, marks) = cell?;
+            if let Some(cell) = notes.get_mut(r).and_then(|cells| cells.get_mut(c)) {
+                *cell = marks;
+            }
+        }
+
+        let puzzle = Sudoku {
+            board: parse_board(&givens).map_err(anyhow::Error::msg)?,
+            solved: parse_board(&solution).map_err(anyhow::Error::msg)?,
+            sudoku_size: from_value_name::<SudokuSize>(&size)?,
+            difficulty: from_value_name::<Difficulty>(&difficulty)?,
+            state: SudokuState::Incomplete,
+        };
+
+        Ok(Some(StoredGame {
+            puzzle,
+            board,
+            user,
+            daily,
+            started_at,
+            finished_at,
+            paused_ms,
+            paused_at,
+            notes,
+            players,
+        }))
+    }
+
+    fn record_move(&self, id: GameId, mv: &Move, user: Option<UserId>, kind: MoveKind, board: &[Vec<u8>]) -> anyhow::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO moves (game_id, seq, row, col, value, at, user_id, kind)
+             VALUES (?1, (SELECT COUNT(*) FROM moves WHERE game_id = ?1), ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![id, mv.row, mv.col, mv.value, mv.at_ms, user, kind.as_str()],
+        )?;
+        tx.execute("UPDATE games SET board = ?2 WHERE id = ?1", params![id, format_board(board)])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn moves(&self, id: GameId) -> anyhow::Result<Vec<StoredMove>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT m.row, m.col, m.value, m.at, u.id, u.username, m.kind
+             FROM moves m LEFT JOIN users u ON u.id = m.user_id
+             WHERE m.game_id = ?1
+             ORDER BY m.seq",
+        )?;
+        let rows = stmt
+            .query_map(params![id], |row| {
+                let user = match row.get::<_, Option<UserId>>(4)? {
+                    Some(user) => Some((user, row.get(5)?)),
+                    None => None,
+                };
+                let mv = Move { row: row.get(0)?, col: row.get(1)?, value: row.get(2)?, at_ms: row.get(3)? };
+                Ok((mv, user, row.get::<_, String>(6)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(mv, user, kind)| Ok(StoredMove { mv, user, kind: MoveKind::parse(&kind)? }))
+            .collect()
+    }
+
+    fn add_player(&self, id: GameId, user: UserId, joined_at: u64) -> anyhow::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR IGNORE INTO game_players (game_id, user_id, joined_at) VALUES (?1, ?2, ?3)",
+            params![id, user, joined_at],
+        )?;
+        Ok(())
+    }
+
+    fn set_paused(&self, id: GameId, paused_at: Option<u64>, paused_ms: u64) -> anyhow::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("UPDATE games SET paused_at = ?2, paused_ms = ?3 WHERE id = ?1", params![id, paused_at, paused_ms])?;
+        Ok(())
+    }
+
+    fn save_notes(&self, id: GameId, cells: &[(u8, u8, u16)]) -> anyhow::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for &(row, col, marks) in cells {
+            match marks {
+                0 => tx.execute("DELETE FROM notes WHERE game_id = ?1 AND row = ?2 AND col = ?3", params![id, row, col])?,
+                _ => tx.execute(
+                    "INSERT OR REPLACE INTO notes (game_id, row, col, marks) VALUES (?1, ?2, ?3, ?4)",
+                    params![id, row, col, marks],
+                )?,
+            };
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn finish_game(&self, id: GameId, finished_at: u64) -> anyhow::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("UPDATE games SET finished_at = ?2 WHERE id = ?1", params![id, finished_at])?;
+        tx.execute(
+            "INSERT OR IGNORE INTO completions (game_id, size, difficulty, millis, completed_at)
+             SELECT g.id, p.size, p.difficulty, ?2 - g.started_at - g.paused_ms, ?2
+             FROM games g JOIN puzzles p ON p.id = g.puzzle_id
+             WHERE g.id = ?1",
+            params![id, finished_at],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn create_user(&self, username: &str, password_hash: &str) -> anyhow::Result<Option<UserId>> {
+        let conn = self.conn.lock().unwrap();
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO users (username, password_hash, created_at) VALUES (?1, ?2, ?3)",
+            params![username, password_hash, now_ms()],
+        )?;
+        Ok((inserted == 1).then(|| conn.last_insert_rowid() as UserId))
+    }
+
+    fn find_user(&self, username: &str) -> anyhow::Result<Option<(UserId, String)>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row(
+            "SELECT id, password_hash FROM users WHERE username = ?1",
+            params![username],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).optional()?)
+    }
+
+    fn create_session(&self, token: &str, user: UserId, expires_at: u64) -> anyhow::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM sessions WHERE expires_at <= ?1", params![now_ms()])?;
+        conn.execute(
+            "INSERT INTO sessions (token, user_id, expires_at) VALUES (?1, ?2, ?3)",
+            params![token, user, expires_at],
+        )?;
+        Ok(())
+    }
+
+    fn session_user(&self, token: &str, now: u64) -> anyhow::Result<Option<(UserId, String)>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row(
+            "SELECT u.id, u.username FROM sessions s JOIN users u ON u.id = s.user_id
+             WHERE s.token = ?1 AND s.expires_at > ?2",
+            params![token, now],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).optional()?)
+    }
+
+    fn leaderboard(&self, size: SudokuSize, difficulty: Difficulty, limit: u32) -> anyhow::Result<Vec<SolveRecord>> {
+        let conn = self.conn.lock().unwrap();
+        // con MIN(), SQLite toma el resto de columnas de la fila mínima
+        let mut stmt = conn.prepare(
+            "SELECT u.username, MIN(c.millis), c.completed_at
+             FROM completions c JOIN games g ON g.id = c.game_id JOIN users u ON u.id = g.user_id
+             WHERE c.size = ?1 AND c.difficulty = ?2
+             GROUP BY u.id
+             ORDER BY MIN(c.millis), c.completed_at
+             LIMIT ?3",
+        )?;
+        let records = stmt
+            .query_map(params![value_name(&size), value_name(&difficulty), limit], solve_record)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(records)
+    }
+
+    fn personal_best(&self, user: UserId, size: SudokuSize, difficulty: Difficulty) -> anyhow::Result<Option<SolveRecord>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row(
+            "SELECT u.username, c.millis, c.completed_at
+             FROM completions c JOIN games g ON g.id = c.game_id JOIN users u ON u.id = g.user_id
+             WHERE g.user_id = ?1 AND c.size = ?2 AND c.difficulty = ?3
+             ORDER BY c.millis, c.completed_at
+             LIMIT 1",
+            params![user, value_name(&size), value_name(&difficulty)],
+            solve_record,
+        ).optional()?)
+    }
+
+    fn daily_game(&self, user: UserId, day: u64, size: SudokuSize) -> anyhow::Result<Option<GameId>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row(
+            "SELECT g.id FROM games g JOIN puzzles p ON p.id = g.puzzle_id
+             WHERE g.daily = ?1 AND g.user_id = ?2 AND p.size = ?3
+             ORDER BY g.started_at LIMIT 1",
+            params![day, user, value_name(&size)],
+            |row| row.get(0),
+        ).optional()?)
+    }
+
+    fn daily_leaderboard(&self, day: u64, size: SudokuSize, limit: u32) -> anyhow::Result<Vec<SolveRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT u.username, c.millis, c.completed_at
+             FROM completions c JOIN games g ON g.id = c.game_id JOIN users u ON u.id = g.user_id
+             WHERE g.daily = ?1 AND c.size = ?2
+             ORDER BY c.millis, c.completed_at
+             LIMIT ?3",
+        )?;
+        let records = stmt
+            .query_map(params![day, value_name(&size), limit], solve_record)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(records)
+    }
+
+    fn daily_days_solved(&self, user: UserId) -> anyhow::Result<Vec<u64>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT g.daily FROM games g JOIN completions c ON c.game_id = g.id
+             WHERE g.user_id = ?1 AND g.daily IS NOT NULL
+             ORDER BY g.daily DESC",
+        )?;
+        let days = stmt.query_map(params![user], |row| row.get(0))?.collect::<Result<Vec<_>, _>>()?;
+        Ok(days)
+    }
+
+    fn flush(&self) -> anyhow::Result<()> {
+        self.conn.lock().unwrap().execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+        Ok(())
+    }
+}
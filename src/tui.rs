@@ -0,0 +1,1491 @@
+use std::io::{stdout};
+use std::time::{Duration, Instant};
+use crossterm::{event::{self, Event, KeyCode, KeyEventKind, MouseButton, MouseEvent, MouseEventKind}, terminal::{enable_raw_mode, disable_raw_mode}, ExecutableCommand};
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Paragraph},
+};
+use ratatui::widgets::Wrap;
+use service::LogBuffers;
+use service::account::Session;
+use service::event::{Event as GameEvent, EventKind};
+use service::game::{Coop, Game, GameId, SolveRecord, Streak};
+use service::race::{RaceState, RaceStatus};
+use service::replay::Replay;
+use service::sudoku::{Difficulty, SudokuSize, SudokuState};
+use tokio::sync::mpsc;
+use tokio::time;
+use tokio::task::JoinHandle;
+use crate::client::RPCClient;
+use crate::grid::{digit_char, digit_value, Grid};
+use crate::logs::LogPane;
+use crate::server::ShutdownHandle;
+
+/// Rows shown in the leaderboard panel.
+const LEADERBOARD_ROWS: u32 = 10;
+
+/// Width of the progress bars in the race panel.
+const RACE_BAR_WIDTH: usize = 20;
+
+/// Events kept in the spectator view.
+const FEED_LINES: usize = 100;
+
+/// Playback speeds of the replay viewer; it starts at 1x.
+const REPLAY_SPEEDS: [f64; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+
+/// Longest wait between two moves of a replay, whatever the recorded pause.
+const MAX_REPLAY_PAUSE: Duration = Duration::from_secs(3);
+
+/// Keys of a focused log pane.
+const LOG_HELP: &str = "↑↓ PgUp PgDn Inicio Fin  f: seguir  /: filtrar  n: nivel  m: maximizar  Tab: otro panel  Esc: volver";
+
+/// Frames of the spinner shown while requests are pending, one every 100 ms.
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// Failed long-polls in a row after which the game and race views stop following the server.
+const FOLLOW_RETRIES: u32 = 5;
+
+/// Log lines one turn of the mouse wheel scrolls.
+const WHEEL_LINES: isize = 3;
+
+/// Colors of the players of a cooperative game, by the order they joined.
+const PLAYER_COLORS: [Color; 8] = [
+    Color::Cyan,
+    Color::Magenta,
+    Color::Yellow,
+    Color::Green,
+    Color::LightBlue,
+    Color::LightRed,
+    Color::LightCyan,
+    Color::LightMagenta,
+];
+
+/// Runs the terminal UI against `client` until the user quits or `shutdown` is triggered,
+/// opening game `resume` or the viewer on `replay` if given. `session` is the player the client
+/// logged in as, if any.
+pub async fn run(
+    client: RPCClient,
+    buffers: LogBuffers,
+    shutdown: ShutdownHandle,
+    session: Option<Session>,
+    resume: Option<GameId>,
+    replay: Option<Replay>,
+) -> anyhow::Result<()> {
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?.execute(EnableMouseCapture)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+
+    let (replies_tx, replies) = mpsc::unbounded_channel();
+    let mut app = App {
+        client,
+        session,
+        game: None,
+        game_at: Instant::now(),
+        leaderboard: Vec::new(),
+        personal_best: None,
+        streak: None,
+        race: None,
+        race_updates: None,
+        race_poller: None,
+        game_events: None,
+        game_poller: None,
+        spectating: false,
+        feed: Vec::new(),
+        replay: replay.map(ReplayView::new),
+        difficulty: Difficulty::default(),
+        logs: [LogPane::new("Cliente"), LogPane::new("Server"), LogPane::new("RPC")],
+        log_focus: None,
+        maximized: false,
+        areas: Areas::default(),
+
+        board_mode: false,
+        cursor: (0, 0),
+        notes_mode: false,
+
+        input_stage: None,
+        input_buffer: String::new(),
+
+        status: None,
+
+        pending: Vec::new(),
+        next_request: 0,
+        last_move: 0,
+        replies_tx,
+        replies,
+    };
+
+    if let Some(id) = resume {
+        app.request("abriendo la partida", move |client| async move { Reply::Game(client.resume_game(id).await) });
+    }
+    app.refresh_leaderboard();
+
+    loop {
+        if shutdown.is_shutdown() {
+            break;
+        }
+
+        app.poll_replies();
+        app.poll_race();
+        app.poll_game();
+        if let Some(replay) = &mut app.replay {
+            replay.tick();
+        }
+
+        terminal.draw(|f| draw_ui(f, &buffers, &mut app))?;
+
+        if event::poll(std::time::Duration::from_millis(50))? {
+            let key = match event::read()? {
+                Event::Key(key) => key,
+                Event::Mouse(mouse) => {
+                    app.mouse(mouse);
+                    continue;
+                }
+                _ => continue,
+            };
+
+            // FILTRO IMPORTANTE
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            // SI ESTAMOS EN MODO INPUT
+            if let Some(stage) = app.input_stage {
+
+                match key.code {
+
+                    KeyCode::Char(c) if stage == InputStage::Message || stage == InputStage::LogFilter => {
+                        app.input_buffer.push(c);
+                    }
+
+                    KeyCode::Char(c) if c.is_ascii_digit() => {
+                        app.input_buffer.push(c);
+                    }
+
+                    KeyCode::Backspace => {
+                        app.input_buffer.pop();
+                    }
+
+                    KeyCode::Enter if stage == InputStage::Room => {
+                        match app.input_buffer.parse() {
+                            Ok(room) => app.request("entrando en la sala", move |client| async move {
+                                Reply::Race(client.join_room(room).await)
+                            }),
+                            Err(_) => app.status = Some("número de sala inválido".to_string()),
+                        }
+                        app.input_stage = None;
+                        app.input_buffer.clear();
+                    }
+
+                    KeyCode::Enter if stage == InputStage::Message => {
+                        if let Some(id) = app.game.as_ref().map(|g| g.id) {
+                            let text = app.input_buffer.clone();
+                            app.request("enviando el mensaje", move |client| async move { Reply::Chat(client.chat(id, text).await) });
+                        }
+                        app.input_stage = None;
+                        app.input_buffer.clear();
+                    }
+
+                    KeyCode::Enter if stage == InputStage::Replay => {
+                        match app.input_buffer.parse() {
+                            Ok(id) => app.request("descargando la repetición", move |client| async move {
+                                Reply::Replay(client.replay(id).await)
+                            }),
+                            Err(_) => app.status = Some("número de partida inválido".to_string()),
+                        }
+                        app.input_stage = None;
+                        app.input_buffer.clear();
+                    }
+
+                    KeyCode::Enter if stage == InputStage::Spectate => {
+                        match app.input_buffer.parse() {
+                            Ok(id) => app.request("abriendo la partida", move |client| async move {
+                                Reply::Spectate(client.spectate(id).await)
+                            }),
+                            Err(_) => app.status = Some("número de partida inválido".to_string()),
+                        }
+                        app.input_stage = None;
+                        app.input_buffer.clear();
+                    }
+
+                    KeyCode::Enter if stage == InputStage::Coop => {
+                        match app.input_buffer.parse() {
+                            Ok(id) => app.request("entrando en la partida", move |client| async move {
+                                Reply::Game(client.join_coop(id).await)
+                            }),
+                            Err(_) => app.status = Some("número de partida inválido".to_string()),
+                        }
+                        app.input_stage = None;
+                        app.input_buffer.clear();
+                    }
+
+                    KeyCode::Esc if stage == InputStage::LogFilter => {
+                        if let Some(i) = app.log_focus {
+                            app.logs[i].filter.clear();
+                        }
+                        app.input_stage = None;
+                        app.input_buffer.clear();
+                    }
+
+                    KeyCode::Enter if stage == InputStage::LogFilter => {
+                        app.input_stage = None;
+                        app.input_buffer.clear();
+                    }
+
+                    KeyCode::Esc => {
+                        app.input_stage = None;
+                        app.input_buffer.clear();
+                    }
+
+                    _ => {}
+                }
+
+                // el filtro se aplica mientras se escribe
+                if app.input_stage == Some(InputStage::LogFilter)
+                    && let Some(i) = app.log_focus {
+                    app.logs[i].filter = app.input_buffer.clone();
+                }
+
+            } else if let Some(replay) = &mut app.replay {
+
+                match key.code {
+                    KeyCode::Char(' ') => replay.toggle(),
+                    KeyCode::Right | KeyCode::Char('n') => replay.step(true),
+                    KeyCode::Left | KeyCode::Char('p') => replay.step(false),
+                    KeyCode::Home => replay.seek(0),
+                    KeyCode::End => replay.seek(replay.replay.moves.len()),
+                    KeyCode::Char('+') => replay.change_speed(true),
+                    KeyCode::Char('-') => replay.change_speed(false),
+                    KeyCode::Esc => app.replay = None,
+                    KeyCode::Char('q') => break,
+                    _ => {}
+                }
+
+            } else if app.board_mode {
+
+                let size = app.game.as_ref().map_or(0, |g| g.sudoku.board.len());
+                let (row, col) = app.cursor;
+                match key.code {
+                    KeyCode::Char('p') => app.pause(),
+                    KeyCode::Esc => app.board_mode = false,
+                    // en pausa el tablero no se ve ni se juega
+                    _ if app.game.as_ref().is_some_and(|g| g.clock.paused) => {}
+                    KeyCode::Up | KeyCode::Char('k') => app.cursor.0 = row.saturating_sub(1),
+                    KeyCode::Down | KeyCode::Char('j') => app.cursor.0 = (row + 1).min(size.saturating_sub(1)),
+                    KeyCode::Left | KeyCode::Char('h') => app.cursor.1 = col.saturating_sub(1),
+                    KeyCode::Right | KeyCode::Char('l') => app.cursor.1 = (col + 1).min(size.saturating_sub(1)),
+                    KeyCode::Delete | KeyCode::Backspace | KeyCode::Char('0' | '.') => app.enter(0),
+                    KeyCode::Char('z') => app.undo(false),
+                    KeyCode::Char('y') => app.undo(true),
+                    KeyCode::Char('n') => app.notes_mode = !app.notes_mode,
+                    KeyCode::Char(c) => {
+                        if let Some(value) = digit_value(c).filter(|&v| v as usize <= size) {
+                            app.enter(value);
+                        }
+                    }
+                    _ => {}
+                }
+
+            } else if let Some(i) = app.log_focus {
+
+                let pane = &mut app.logs[i];
+                match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => pane.scroll(-1),
+                    KeyCode::Down | KeyCode::Char('j') => pane.scroll(1),
+                    KeyCode::PageUp => pane.page(true),
+                    KeyCode::PageDown => pane.page(false),
+                    KeyCode::Home | KeyCode::Char('g') => pane.scroll_to_top(),
+                    KeyCode::End | KeyCode::Char('G') => pane.scroll(isize::MAX),
+                    KeyCode::Char('f') => pane.toggle_follow(),
+                    KeyCode::Char('n') => pane.cycle_level(),
+                    KeyCode::Char('/') => {
+                        app.input_stage = Some(InputStage::LogFilter);
+                        app.input_buffer = pane.filter.clone();
+                    }
+                    KeyCode::Char('m') => app.maximized = !app.maximized,
+                    KeyCode::Tab => app.log_focus = Some((i + 1) % app.logs.len()),
+                    KeyCode::BackTab => app.log_focus = Some((i + app.logs.len() - 1) % app.logs.len()),
+                    KeyCode::Esc => {
+                        app.log_focus = None;
+                        app.maximized = false;
+                    }
+                    _ => {}
+                }
+
+            } else if app.spectating {
+
+                match key.code {
+                    KeyCode::Esc => app.stop_spectating(),
+                    KeyCode::Char('q') => break,
+                    _ => {}
+                }
+
+            } else {
+
+                match key.code {
+
+                    // en el menú Esc cancela la petición que se está esperando
+                    KeyCode::Esc => app.cancel_request(),
+
+                    KeyCode::Char('1') => {
+                        let difficulty = app.difficulty;
+                        app.request("generando un sudoku 4x4", move |client| async move {
+                            Reply::Game(client.start_game(SudokuSize::SUDOKU4X4, difficulty).await)
+                        });
+                    },
+
+                    KeyCode::Char('2') => {
+                        let difficulty = app.difficulty;
+                        app.request("generando un sudoku 9x9", move |client| async move {
+                            Reply::Game(client.start_game(SudokuSize::SUDOKU9X9, difficulty).await)
+                        });
+                    },
+
+                    KeyCode::Char('3') => {
+                        let difficulty = app.difficulty;
+                        app.request("generando un sudoku 16x16", move |client| async move {
+                            Reply::Game(client.start_game(SudokuSize::SUDOKU16X16, difficulty).await)
+                        });
+                    },
+
+                    KeyCode::Char('h') => {
+                        app.request("abriendo el sudoku del día", |client| async move {
+                            Reply::Game(client.daily(SudokuSize::SUDOKU9X9).await)
+                        });
+                    },
+
+                    KeyCode::Char('d') => {
+                        app.difficulty = match app.difficulty {
+                            Difficulty::Easy => Difficulty::Medium,
+                            Difficulty::Medium => Difficulty::Hard,
+                            Difficulty::Hard => Difficulty::Easy,
+                        };
+                    },
+
+                    KeyCode::Char('4') => {
+                        app.board_mode = app.game.is_some();
+                    },
+
+                    KeyCode::Char('5') => {
+                        if let Some(id) = app.game.as_ref().map(|g| g.id) {
+                            app.request("verificando", move |client| async move { Reply::Game(client.resume_game(id).await) });
+                        }
+                    }
+
+                    KeyCode::Char('l') => {
+                        app.refresh_leaderboard();
+                    }
+
+                    KeyCode::Char('z') => app.undo(false),
+                    KeyCode::Char('y') => app.undo(true),
+                    KeyCode::Char('p') => app.pause(),
+
+                    KeyCode::Char('o') => {
+                        let difficulty = app.difficulty;
+                        app.request("creando la partida", move |client| async move {
+                            Reply::Game(client.start_coop(SudokuSize::SUDOKU9X9, difficulty).await)
+                        });
+                    }
+
+                    KeyCode::Char('u') => {
+                        app.input_stage = Some(InputStage::Coop);
+                        app.input_buffer.clear();
+                    }
+
+                    KeyCode::Char('m') if app.game.as_ref().is_some_and(|g| g.coop.is_some()) => {
+                        app.input_stage = Some(InputStage::Message);
+                        app.input_buffer.clear();
+                    }
+
+                    KeyCode::Char('r') => {
+                        app.input_stage = Some(InputStage::Replay);
+                        app.input_buffer.clear();
+                    }
+
+                    KeyCode::Char('v') => {
+                        app.input_stage = Some(InputStage::Spectate);
+                        app.input_buffer.clear();
+                    }
+
+                    KeyCode::Char('c') => {
+                        let difficulty = app.difficulty;
+                        app.request("creando la sala", move |client| async move {
+                            Reply::Race(client.create_room(SudokuSize::SUDOKU9X9, difficulty).await)
+                        });
+                    }
+
+                    KeyCode::Char('j') => {
+                        app.input_stage = Some(InputStage::Room);
+                        app.input_buffer.clear();
+                    }
+
+                    KeyCode::Char('s') => {
+                        if let Some(room) = app.race.as_ref().map(|race| race.room) {
+                            app.request("empezando la carrera", move |client| async move {
+                                Reply::Race(client.start_race(room).await)
+                            });
+                        }
+                    }
+
+                    KeyCode::Tab => app.log_focus = Some(0),
+
+                    KeyCode::Char('q') | KeyCode::Char('6') => {
+                        break;
+                    }
+
+                    _ => {}
+                }
+            }
+
+        }
+
+    }
+
+    for poller in [app.race_poller.take(), app.game_poller.take()].into_iter().flatten() {
+        poller.abort();
+    }
+
+    disable_raw_mode()?;
+    stdout().execute(DisableMouseCapture)?.execute(LeaveAlternateScreen)?;
+    // ratatui::restore();
+
+    Ok(())
+}
+
+/// Playback state of the replay viewer.
+struct ReplayView {
+    replay: Replay,
+    /// Moves applied to the board shown.
+    shown: usize,
+    playing: bool,
+    /// Index in `REPLAY_SPEEDS`.
+    speed: usize,
+    /// When the next move is due while playing.
+    next_at: Instant,
+}
+
+impl ReplayView {
+    fn new(replay: Replay) -> Self {
+        let mut view = ReplayView { replay, shown: 0, playing: true, speed: 2, next_at: Instant::now() };
+        view.schedule();
+        view
+    }
+
+    /// Waits for the next move as long as it took in the game, scaled by the speed.
+    fn schedule(&mut self) {
+        let Some(next) = self.replay.moves.get(self.shown) else {
+            self.playing = false;
+            return;
+        };
+        let previous = match self.shown {
+            0 => self.replay.started_at,
+            n => self.replay.moves[n - 1].mv.at_ms,
+        };
+        let pause = Duration::from_millis(next.mv.at_ms.saturating_sub(previous)).div_f64(REPLAY_SPEEDS[self.speed]);
+        self.next_at = Instant::now() + pause.min(MAX_REPLAY_PAUSE);
+    }
+
+    /// Plays the next move when it is due.
+    fn tick(&mut self) {
+        if self.playing && Instant::now() >= self.next_at {
+            self.shown += 1;
+            self.schedule();
+        }
+    }
+
+    fn toggle(&mut self) {
+        if !self.playing && self.shown == self.replay.moves.len() {
+            self.shown = 0;
+        }
+        self.playing = !self.playing;
+        self.schedule();
+    }
+
+    fn step(&mut self, forward: bool) {
+        let shown = if forward { self.shown + 1 } else { self.shown.saturating_sub(1) };
+        self.seek(shown);
+    }
+
+    /// Shows the board after `moves` moves, paused.
+    fn seek(&mut self, moves: usize) {
+        self.shown = moves.min(self.replay.moves.len());
+        self.playing = false;
+    }
+
+    fn change_speed(&mut self, faster: bool) {
+        self.speed = if faster { (self.speed + 1).min(REPLAY_SPEEDS.len() - 1) } else { self.speed.saturating_sub(1) };
+        if self.playing {
+            self.schedule();
+        }
+    }
+}
+
+/// A request sent in the background that has not been answered yet.
+struct Pending {
+    id: u64,
+    kind: RequestKind,
+    what: &'static str,
+    since: Instant,
+    task: JoinHandle<()>,
+}
+
+/// What a pending request is for, which decides whether Esc can cancel it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RequestKind {
+    /// Something the user asked for and waits on, like opening a game.
+    Wait,
+    /// A move, undo, redo, note or pause on the open game.
+    Move,
+    /// Kept up to date without the user asking, like the ranking.
+    Background,
+}
+
+/// What the text box is asking for while it has the keyboard.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum InputStage {
+    /// The number of a race room to join.
+    Room,
+    /// The id of a cooperative game to join.
+    Coop,
+    /// A chat message for the players of the open game.
+    Message,
+    /// The id of a game to watch.
+    Spectate,
+    /// The id of a game whose replay to download.
+    Replay,
+    /// The filter of the focused log pane.
+    LogFilter,
+}
+
+/// Answer to a background request, with what is needed to apply it.
+enum Reply {
+    /// A game to open: started, resumed, joined or the daily one.
+    Game(anyhow::Result<Game>),
+    Spectate(anyhow::Result<Game>),
+    /// The game after a move, an undo or redo, or pausing it.
+    Moved(anyhow::Result<Game>),
+    Race(anyhow::Result<RaceStatus>),
+    Replay(anyhow::Result<Replay>),
+    Chat(anyhow::Result<()>),
+    Ranking(Ranking),
+}
+
+/// What the leaderboard panel shows. The player's best time and streak are only asked for when
+/// logged in, and the streak only for the daily puzzle.
+struct Ranking {
+    records: anyhow::Result<Vec<SolveRecord>>,
+    personal_best: Option<anyhow::Result<Option<SolveRecord>>>,
+    streak: Option<anyhow::Result<Streak>>,
+}
+
+/// Screen areas of the main view the mouse can act on.
+#[derive(Default)]
+struct Areas {
+    board: Rect,
+    palette: Rect,
+    logs: [Rect; 3],
+}
+
+struct App {
+    client: RPCClient,
+    session: Option<Session>,
+    game: Option<Game>,
+    game_at: Instant, // cuándo llegó `game`; su reloj avanza desde entonces
+    leaderboard: Vec<SolveRecord>, // del tamaño y dificultad de leaderboard_slot(), o del diario
+    personal_best: Option<SolveRecord>,
+    streak: Option<Streak>,
+    race: Option<RaceStatus>,
+    race_updates: Option<mpsc::UnboundedReceiver<RaceStatus>>,
+    race_poller: Option<JoinHandle<()>>,
+    game_events: Option<mpsc::UnboundedReceiver<Vec<GameEvent>>>, // de la partida cooperativa abierta
+    game_poller: Option<JoinHandle<()>>,
+    spectating: bool, // `game` es de otro y solo se mira
+    feed: Vec<String>, // últimos eventos de la partida seguida
+    replay: Option<ReplayView>, // visor de repeticiones abierto
+    difficulty: Difficulty,
+    logs: [LogPane; 3], // cliente, servidor y RPC
+    log_focus: Option<usize>, // panel de logs que recibe las teclas
+    maximized: bool, // el panel con el foco ocupa toda la pantalla
+    areas: Areas, // dónde se dibujó cada cosa en el último frame, para el ratón
+
+    board_mode: bool, // las teclas mueven el cursor y escriben en el tablero
+    cursor: (usize, usize), // fila y columna seleccionadas
+    notes_mode: bool, // los dígitos anotan candidatos en vez de escribir
+
+    input_stage: Option<InputStage>, // qué se está escribiendo en el cuadro de texto, si algo
+    input_buffer: String,
+
+    status: Option<String>, // último error del servidor
+
+    pending: Vec<Pending>, // peticiones enviadas sin respuesta
+    next_request: u64,
+    last_move: u64, // la última jugada enviada; las respuestas a otras anteriores ya no valen
+    replies_tx: mpsc::UnboundedSender<(u64, Reply)>,
+    replies: mpsc::UnboundedReceiver<(u64, Reply)>,
+}
+
+impl App {
+    /// Shows a game just started or reloaded, or why the request failed.
+    fn set_game(&mut self, result: anyhow::Result<Game>) {
+        let game = match result {
+            Ok(game) => game,
+            Err(err) => return self.show_error(err),
+        };
+        self.status = None;
+
+        if let Some(poller) = self.game_poller.take() {
+            poller.abort();
+        }
+        self.game_events = None;
+        if game.coop.is_some() || self.spectating {
+            let (events, poller) = follow_events(self.client.clone(), game.id);
+            self.game_events = Some(events);
+            self.game_poller = Some(poller);
+        }
+        if self.game.as_ref().map(|g| g.id) != Some(game.id) {
+            self.cursor = (0, 0);
+        }
+        self.game = Some(game);
+        self.game_at = Instant::now();
+    }
+
+    /// Whether the open game was followed and its poller gave up.
+    fn game_updates_stopped(&self) -> bool {
+        self.game_events.as_ref().is_some_and(|events| events.is_closed())
+    }
+
+    /// Whether the race had not finished when its poller gave up.
+    fn race_updates_stopped(&self) -> bool {
+        let finished = self.race.as_ref().is_some_and(|race| matches!(race.state, RaceState::Finished { .. }));
+        !finished && self.race_updates.as_ref().is_some_and(|updates| updates.is_closed())
+    }
+
+    /// Time played in the open game: the server's clock plus what has passed since it arrived,
+    /// unless the game is paused or solved.
+    fn elapsed(&self) -> u64 {
+        let Some(game) = &self.game else { return 0 };
+        match game.clock.paused || game.sudoku.state == SudokuState::Solved {
+            true => game.clock.elapsed_ms,
+            false => game.clock.elapsed_ms + self.game_at.elapsed().as_millis() as u64,
+        }
+    }
+
+    /// Sends a request in the background with a clone of the client. Its reply is handled by
+    /// [`App::poll_replies`]; until then `what` shows in the status line.
+    fn request<F, Fut>(&mut self, what: &'static str, call: F)
+    where
+        F: FnOnce(RPCClient) -> Fut,
+        Fut: Future<Output = Reply> + Send + 'static,
+    {
+        self.send(RequestKind::Wait, what, call);
+    }
+
+    /// Like [`App::request`] for a move on the open game. Only the reply to the last move sent
+    /// is applied, so one arriving late cannot undo a newer one on screen.
+    fn request_move<F, Fut>(&mut self, what: &'static str, call: F)
+    where
+        F: FnOnce(RPCClient) -> Fut,
+        Fut: Future<Output = Reply> + Send + 'static,
+    {
+        self.last_move = self.send(RequestKind::Move, what, call);
+    }
+
+    fn send<F, Fut>(&mut self, kind: RequestKind, what: &'static str, call: F) -> u64
+    where
+        F: FnOnce(RPCClient) -> Fut,
+        Fut: Future<Output = Reply> + Send + 'static,
+    {
+        let id = self.next_request;
+        self.next_request += 1;
+        let replies = self.replies_tx.clone();
+        let call = call(self.client.clone());
+        let task = tokio::spawn(async move {
+            let _ = replies.send((id, call.await));
+        });
+        self.pending.push(Pending { id, kind, what, since: Instant::now(), task });
+        id
+    }
+
+    /// The request shown as the one the user waits on: the oldest not sent in the background.
+    fn awaited(&self) -> Option<&Pending> {
+        self.pending.iter().find(|pending| pending.kind != RequestKind::Background)
+    }
+
+    /// Drops the request the user waits on; the server is told it was cancelled. The game is
+    /// fetched again after a cancelled move, since the server may have played it anyway.
+    fn cancel_request(&mut self) {
+        let Some(i) = self.pending.iter().position(|pending| pending.kind != RequestKind::Background) else { return };
+        let pending = self.pending.remove(i);
+        pending.task.abort();
+        tracing::info!(target: "cliente", "Cancelado: {}", pending.what);
+        self.status = Some("petición cancelada".to_string());
+        if pending.kind == RequestKind::Move
+            && let Some(game) = &self.game
+        {
+            let id = game.id;
+            self.request("recargando la partida", move |client| async move { Reply::Game(client.resume_game(id).await) });
+        }
+    }
+
+    /// Handles the replies received since the last frame.
+    fn poll_replies(&mut self) {
+        while let Ok((id, reply)) = self.replies.try_recv() {
+            self.pending.retain(|pending| pending.id != id);
+            match reply {
+                Reply::Game(result) => {
+                    let opened = result.is_ok();
+                    self.set_game(result);
+                    if opened {
+                        self.refresh_leaderboard();
+                    }
+                }
+                Reply::Spectate(result) => self.spectate(result),
+                Reply::Moved(result) => match result {
+                    Ok(moved) if id == self.last_move => self.moved(moved),
+                    Ok(_) => {}
+                    Err(err) => self.show_error(err),
+                },
+                Reply::Race(result) => self.set_race(result),
+                Reply::Replay(result) => match result {
+                    Ok(replay) => self.replay = Some(ReplayView::new(replay)),
+                    Err(err) => self.show_error(err),
+                },
+                Reply::Chat(result) => {
+                    if let Err(err) = result {
+                        self.show_error(err);
+                    }
+                }
+                Reply::Ranking(ranking) => {
+                    match ranking.records {
+                        Ok(records) => self.leaderboard = records,
+                        Err(err) => self.show_error(err),
+                    }
+                    match ranking.personal_best {
+                        Some(Ok(best)) => self.personal_best = best,
+                        Some(Err(err)) => self.show_error(err),
+                        None => {}
+                    }
+                    match ranking.streak {
+                        Some(Ok(streak)) => self.streak = Some(streak),
+                        Some(Err(err)) => self.show_error(err),
+                        None => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Takes the server's copy of the open game after a move. When that move solved it, logs
+    /// the final time and shows the leaderboard it went into.
+    fn moved(&mut self, moved: Game) {
+        let Some(game) = &self.game else { return };
+        if game.id != moved.id {
+            return;
+        }
+        let solved = game.sudoku.state != SudokuState::Solved && moved.sudoku.state == SudokuState::Solved;
+        self.game = Some(moved);
+        self.game_at = Instant::now();
+        if solved {
+            tracing::info!(target: "cliente", "¡Resuelto en {}!", format_millis(self.elapsed()));
+            self.refresh_leaderboard();
+        }
+    }
+
+    /// What a digit does on the board: writes it in the cell under the cursor, or in notes mode
+    /// adds it to the cell's notes or takes it out. 0 clears the value or the notes.
+    fn enter(&mut self, value: u8) {
+        if self.notes_mode { self.note(value) } else { self.place(value) }
+    }
+
+    /// Writes `value` (0 clears) in the cell under the cursor.
+    fn place(&mut self, value: u8) {
+        let Some(game) = &self.game else { return };
+        let mut game = game.clone();
+        let (row, col) = (self.cursor.0 as u8, self.cursor.1 as u8);
+        self.status = None;
+        self.request_move("enviando la jugada", move |client| async move {
+            Reply::Moved(client.place(&mut game, row, col, value).await.map(|()| game))
+        });
+    }
+
+    /// Adds `value` to the notes of the cell under the cursor or takes it out; 0 clears them.
+    fn note(&mut self, value: u8) {
+        let Some(game) = &self.game else { return };
+        let mut game = game.clone();
+        let (row, col) = (self.cursor.0 as u8, self.cursor.1 as u8);
+        self.status = None;
+        self.request_move("anotando", move |client| async move {
+            Reply::Moved(client.note(&mut game, row, col, value).await.map(|()| game))
+        });
+    }
+
+    /// Stops the clock and hides the board, or shows it again and lets the clock run.
+    fn pause(&mut self) {
+        let Some(game) = &self.game else { return };
+        let mut game = game.clone();
+        let paused = !game.clock.paused;
+        self.status = None;
+        self.request_move(if paused { "pausando" } else { "reanudando" }, move |client| async move {
+            Reply::Moved(client.pause(&mut game, paused).await.map(|()| game))
+        });
+    }
+
+    /// Clicking a cell selects it and clicking the palette writes in the selected cell, both
+    /// handing the keyboard to the board. The wheel scrolls the log pane under it.
+    fn mouse(&mut self, mouse: MouseEvent) {
+        if self.replay.is_some() || self.spectating || self.input_stage.is_some() || self.maximized {
+            return;
+        }
+        let (x, y) = (mouse.column, mouse.row);
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if let Some(i) = self.areas.logs.iter().position(|&area| contains(area, x, y)) {
+                    self.log_focus = Some(i);
+                    self.board_mode = false;
+                    return;
+                }
+                let Some(game) = self.game.as_ref().filter(|game| !game.clock.paused) else { return };
+                let size = game.sudoku.board.len();
+                if let Some(cell) = board_grid(game).cell_at(self.areas.board, x, y) {
+                    self.cursor = cell;
+                    self.board_mode = true;
+                } else if let Some(value) = palette_value(self.areas.palette, size, x, y) {
+                    self.board_mode = true;
+                    self.enter(value);
+                }
+            }
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                for (&area, pane) in self.areas.logs.iter().zip(&mut self.logs) {
+                    if contains(area, x, y) {
+                        pane.scroll(if mouse.kind == MouseEventKind::ScrollUp { -WHEEL_LINES } else { WHEEL_LINES });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Takes back the player's last move, or plays again the last one taken back if `redo`.
+    fn undo(&mut self, redo: bool) {
+        let Some(game) = &self.game else { return };
+        let mut game = game.clone();
+        self.status = None;
+        self.request_move(if redo { "rehaciendo" } else { "deshaciendo" }, move |client| async move {
+            let result = if redo { client.redo(&mut game).await } else { client.undo(&mut game).await };
+            Reply::Moved(result.map(|()| game))
+        });
+    }
+
+    /// Switches to the spectator view of the game just received.
+    fn spectate(&mut self, result: anyhow::Result<Game>) {
+        if result.is_ok() {
+            self.spectating = true;
+            self.feed.clear();
+        }
+        self.set_game(result);
+    }
+
+    fn stop_spectating(&mut self) {
+        if let Some(poller) = self.game_poller.take() {
+            poller.abort();
+        }
+        self.game_events = None;
+        self.game = None;
+        self.spectating = false;
+    }
+
+    /// Applies what the other players of the followed game did since the last frame and shows
+    /// their messages in the client log.
+    fn poll_game(&mut self) {
+        let elapsed = self.elapsed();
+        let (Some(events), Some(game)) = (&mut self.game_events, &mut self.game) else { return };
+        while let Ok(batch) = events.try_recv() {
+            for event in &batch {
+                if matches!(event.kind, EventKind::Completed { .. }) {
+                    game.clock.elapsed_ms = elapsed;
+                }
+                game.apply(event);
+                if let EventKind::Chat { player, text } = &event.kind {
+                    tracing::info!(target: "cliente", "[{}] {player}: {text}", game.id);
+                }
+                if self.feed.len() == FEED_LINES {
+                    self.feed.remove(0);
+                }
+                self.feed.push(event.kind.to_string());
+            }
+        }
+    }
+
+    /// Shows the room just created, joined or started and follows it from then on.
+    fn set_race(&mut self, result: anyhow::Result<RaceStatus>) {
+        let status = match result {
+            Ok(status) => status,
+            Err(err) => return self.show_error(err),
+        };
+        self.status = None;
+
+        if let Some(poller) = self.race_poller.take() {
+            poller.abort();
+        }
+        let (updates, poller) = follow_race(self.client.clone(), &status);
+        self.race_updates = Some(updates);
+        self.race_poller = Some(poller);
+        self.race = Some(status);
+    }
+
+    /// Takes the race updates received since the last frame. When the race starts, opens this
+    /// player's game.
+    fn poll_race(&mut self) {
+        let Some(updates) = &mut self.race_updates else { return };
+        let mut latest = None;
+        while let Ok(status) = updates.try_recv() {
+            latest = Some(status);
+        }
+        let Some(status) = latest else { return };
+
+        let me = self.session.as_ref().map(|session| session.username.as_str());
+        let mine = status.racers.iter().find(|racer| Some(racer.username.as_str()) == me).and_then(|racer| racer.game_id);
+        let finished = matches!(status.state, RaceState::Finished { .. });
+        self.race = Some(status);
+
+        if let Some(id) = mine && self.game.as_ref().map(|g| g.id) != Some(id) {
+            self.request("abriendo tu partida", move |client| async move { Reply::Game(client.resume_game(id).await) });
+        }
+        if finished {
+            self.refresh_leaderboard();
+        }
+    }
+
+    /// Size and difficulty the leaderboard panel shows: the current game's, or 9x9 at the
+    /// selected difficulty when there is none.
+    fn leaderboard_slot(&self) -> (SudokuSize, Difficulty) {
+        match &self.game {
+            Some(game) => (game.sudoku.sudoku_size, game.sudoku.difficulty),
+            None => (SudokuSize::SUDOKU9X9, self.difficulty),
+        }
+    }
+
+    fn is_daily(&self) -> bool {
+        self.game.as_ref().is_some_and(|g| g.daily.is_some())
+    }
+
+    /// Asks for the leaderboard of `leaderboard_slot()`, or the daily one, and the player's best
+    /// time or streak.
+    fn refresh_leaderboard(&mut self) {
+        let (size, difficulty) = self.leaderboard_slot();
+        let daily = self.is_daily();
+        let logged_in = self.session.is_some();
+
+        self.send(RequestKind::Background, "actualizando el ranking", move |client| async move {
+            let ranking = if daily {
+                Ranking {
+                    records: client.daily_leaderboard(size, LEADERBOARD_ROWS).await,
+                    personal_best: None,
+                    streak: match logged_in {
+                        true => Some(client.streak().await),
+                        false => None,
+                    },
+                }
+            } else {
+                Ranking {
+                    records: client.leaderboard(size, difficulty, LEADERBOARD_ROWS).await,
+                    personal_best: match logged_in {
+                        true => Some(client.personal_best(size, difficulty).await),
+                        false => None,
+                    },
+                    streak: None,
+                }
+            };
+            Reply::Ranking(ranking)
+        });
+    }
+
+    fn show_error(&mut self, err: anyhow::Error) {
+        tracing::error!(target: "cliente", "{err}");
+        self.status = Some(err.to_string());
+    }
+}
+
+
+fn draw_ui(frame: &mut Frame, buffers: &LogBuffers, app: &mut App) {
+    if let Some(replay) = &app.replay {
+        return draw_replay(frame, replay);
+    }
+    if app.spectating {
+        return draw_spectator(frame, app);
+    }
+    if let Some(i) = app.log_focus.filter(|_| app.maximized) {
+        return draw_maximized(frame, buffers, app, i);
+    }
+
+    // jugando en el tablero se oculta el menú y el tablero gana sitio para las notas
+    let left_width = if app.board_mode { (frame.size().width / 2).max(50) } else { 50 };
+    let layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Length(left_width),
+            Constraint::Min(50),
+        ])
+        .split(frame.size());
+
+    let left = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(if app.board_mode { 0 } else { 22 }), // menú
+            Constraint::Length(3),  // input (nuevo)
+            Constraint::Min(10),    // sudoku
+            Constraint::Length(3),  // números para el ratón
+        ])
+        .split(layout[0]);
+
+    let menu = Paragraph::new(format!(
+            "1. Sudoku 4x4 \
+            \n2. Sudoku 9x9 \
+            \n3. Sudoku 16x16 \
+            \nh. Sudoku del día (9x9) \
+            \nd. Dificultad: {:?} \
+            \n4. Jugar en el tablero (Esc sale) \
+            \n5. Verificar sudoku \
+            \nz. Deshacer / y. Rehacer \
+            \np. Pausa \
+            \nTab. Paneles de logs \
+            \nl. Actualizar ranking \
+            \no. Partida cooperativa (9x9) \
+            \nu. Unirse a una cooperativa \
+            \nm. Mensaje a los jugadores \
+            \nv. Ver una partida como espectador \
+            \nr. Repetición de una partida \
+            \nc. Crear sala de carrera (9x9) \
+            \nj. Unirse a una sala \
+            \ns. Empezar la carrera \
+            \n6 o q. Salir",
+            app.difficulty
+        ))
+        .wrap(Wrap::default())
+        .block(Block::default().title(match &app.session {
+            Some(session) => format!("Menu - {}", session.username),
+            None => "Menu - anónimo".to_string(),
+        }).borders(Borders::ALL));
+
+    frame.render_widget(menu, left[0]);
+
+    match &app.game {
+        Some(game) if game.clock.paused => {
+            let paused = Paragraph::new(format!("\nEn pausa ({})\n\np: continuar", format_clock(game.clock.elapsed_ms)))
+                .block(Block::default().title(format!("Sudoku - partida {}", game.id)).borders(Borders::ALL))
+                .alignment(Alignment::Center);
+            frame.render_widget(paused, left[2]);
+        }
+        Some(game) => {
+            let kind = if game.coop.is_some() { "partida cooperativa" } else { "partida" };
+            let stopped = if app.game_updates_stopped() { " (sin actualizaciones)" } else { "" };
+            let ranked = app.session.is_some() && game.coop.is_none();
+            let sudoku = sudoku_widget(game, app.board_mode.then_some(app.cursor), app.elapsed(), ranked)
+                .block(Block::default().title(format!("Sudoku - {kind} {}{stopped}", game.id)).borders(Borders::ALL));
+            frame.render_widget(sudoku, left[2]);
+            frame.render_widget(palette_widget(game.sudoku.board.len()), left[3]);
+        }
+        None => frame.render_widget(no_sudoku(), left[2]),
+    }
+    app.areas.board = left[2];
+    app.areas.palette = left[3];
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Min(30),    // logs
+            Constraint::Length(34), // ranking
+        ])
+        .split(layout[1]);
+
+    match &app.race {
+        Some(race) => frame.render_widget(race_widget(race, app.race_updates_stopped()), columns[1]),
+        None => frame.render_widget(leaderboard_widget(app), columns[1]),
+    }
+
+    let right = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(40),
+            Constraint::Percentage(10),
+            Constraint::Percentage(50),
+        ])
+        .split(columns[0]);
+
+    app.areas.logs = [right[0], right[1], right[2]];
+    for (i, entries) in [&buffers.client, &buffers.server, &buffers.rpc].into_iter().enumerate() {
+        let entries = entries.lock().unwrap();
+        let pane = app.logs[i].widget(&entries, right[i], app.log_focus == Some(i));
+        frame.render_widget(pane, right[i]);
+    }
+
+    if let Some(stage) = app.input_stage {
+        let title = match stage {
+            InputStage::Room => "Sala",
+            InputStage::Coop => "Partida cooperativa",
+            InputStage::Message => "Mensaje",
+            InputStage::Spectate => "Ver partida",
+            InputStage::Replay => "Repetición de la partida",
+            InputStage::LogFilter => "Filtrar logs",
+        };
+
+        let input = Paragraph::new(app.input_buffer.clone())
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .alignment(Alignment::Center);
+
+        frame.render_widget(input, left[1]);
+    } else if let Some(oldest) = app.awaited() {
+        let elapsed = oldest.since.elapsed();
+        let spinner = SPINNER[(elapsed.as_millis() / 100) as usize % SPINNER.len()];
+        let more = match app.pending.iter().filter(|pending| pending.kind != RequestKind::Background).count() {
+            1 => String::new(),
+            n => format!(" (+{})", n - 1),
+        };
+        // Esc solo cancela desde el menú; en los demás sitios conserva su significado
+        let in_menu = !app.board_mode && app.log_focus.is_none() && app.replay.is_none() && !app.spectating;
+        let title = if in_menu { "Esperando (Esc cancela)" } else { "Esperando" };
+        let waiting = Paragraph::new(format!("{spinner} {}{more} {:.1} s", oldest.what, elapsed.as_secs_f32()))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(waiting, left[1]);
+    } else if app.log_focus.is_some() {
+        let help = Paragraph::new("f seguir  / filtrar  n nivel  m maximizar")
+            .block(Block::default().borders(Borders::ALL).title("Logs (Esc sale)"));
+        frame.render_widget(help, left[1]);
+    } else if app.board_mode && app.status.is_none() {
+        let letters = if app.game.as_ref().is_some_and(|g| g.sudoku.board.len() > 9) { " a-g" } else { "" };
+        let (action, title) = match app.notes_mode {
+            true => ("anotar", "Tablero - notas (n: valores)"),
+            false => ("escribir", "Tablero (n: notas)"),
+        };
+        let help = Paragraph::new(format!("flechas/hjkl: mover  1-9{letters}: {action}  Supr: borrar"))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(help, left[1]);
+    }else {
+        let status = Paragraph::new(app.status.clone().unwrap_or_default())
+            .style(Style::default().fg(Color::Red))
+            .block(Block::default().borders(Borders::ALL).title("Input"));
+        frame.render_widget(status, left[1]);
+    }
+}
+
+/// Log pane `i` on the whole screen.
+fn draw_maximized(frame: &mut Frame, buffers: &LogBuffers, app: &mut App, i: usize) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(3),
+            Constraint::Length(1), // ayuda
+        ])
+        .split(frame.size());
+
+    let entries = [&buffers.client, &buffers.server, &buffers.rpc][i].lock().unwrap();
+    frame.render_widget(app.logs[i].widget(&entries, rows[0], true), rows[0]);
+
+    let help = match app.input_stage.is_some() {
+        true => format!("Filtrar: {}   Enter: aceptar   Esc: quitar el filtro", app.input_buffer),
+        false => LOG_HELP.to_string(),
+    };
+    frame.render_widget(Paragraph::new(help), rows[1]);
+}
+
+/// Replay viewer: the board as it was after the moves played so far.
+fn draw_replay(frame: &mut Frame, view: &ReplayView) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(10),
+            Constraint::Length(1), // ayuda
+        ])
+        .split(frame.size());
+
+    let replay = &view.replay;
+    let last = view.shown.checked_sub(1).map(|i| &replay.moves[i]);
+    let elapsed = last.map_or(0, |m| m.mv.at_ms.saturating_sub(replay.started_at));
+    let last_move = match last {
+        Some(m) => format!(
+            "{} pone {} en ({}, {})",
+            m.player.as_deref().unwrap_or("anónimo"),
+            m.mv.value,
+            m.mv.row,
+            m.mv.col,
+        ),
+        None => "Tablero inicial".to_string(),
+    };
+
+    let board = replay.board_at(view.shown);
+    let footer = vec![
+        Line::from(format!(
+            "Jugada {}/{}   {}   x{}   {}",
+            view.shown,
+            replay.moves.len(),
+            format_millis(elapsed),
+            REPLAY_SPEEDS[view.speed],
+            if view.playing { "reproduciendo" } else { "en pausa" },
+        )),
+        Line::from(last_move),
+    ];
+    let board = Grid::new(&board, &replay.puzzle)
+        .footer(footer)
+        .block(Block::default().title(format!(
+            "Repetición - partida {} ({n}x{n} {:?})",
+            replay.game_id,
+            replay.difficulty,
+            n = replay.size.box_size().pow(2),
+        )).borders(Borders::ALL));
+    frame.render_widget(board, rows[0]);
+
+    frame.render_widget(
+        Paragraph::new("Espacio: reproducir/pausa   ←/→: jugada anterior/siguiente   Inicio/Fin   +/-: velocidad   Esc: volver"),
+        rows[1],
+    );
+}
+
+/// Spectator layout: the watched board and what happens in it, without menu or logs.
+fn draw_spectator(frame: &mut Frame, app: &App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(10),
+            Constraint::Length(1), // ayuda
+        ])
+        .split(frame.size());
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Min(50),    // tablero
+            Constraint::Length(40), // eventos
+        ])
+        .split(rows[0]);
+
+    match &app.game {
+        Some(game) => {
+            let stopped = if app.game_updates_stopped() { " (sin actualizaciones)" } else { "" };
+            let sudoku = sudoku_widget(game, None, app.elapsed(), false)
+                .block(Block::default().title(format!("Espectador - partida {}{stopped}", game.id)).borders(Borders::ALL));
+            frame.render_widget(sudoku, columns[0]);
+        }
+        None => frame.render_widget(no_sudoku(), columns[0]),
+    }
+
+    let shown = columns[1].height.saturating_sub(2) as usize;
+    let feed = app.feed[app.feed.len().saturating_sub(shown)..].join("\n");
+    let feed = Paragraph::new(feed)
+        .wrap(Wrap::default())
+        .block(Block::default().title("Eventos").borders(Borders::ALL));
+    frame.render_widget(feed, columns[1]);
+
+    frame.render_widget(Paragraph::new("Esc: volver al menú   q: salir"), rows[1]);
+}
+
+fn leaderboard_widget(app: &App) -> Paragraph<'static> {
+    let (size, difficulty) = app.leaderboard_slot();
+
+    let mut text = String::new();
+    for (i, record) in app.leaderboard.iter().enumerate() {
+        text.push_str(&format!("{:>2}. {:<18} {}\n", i + 1, record.username, format_millis(record.millis)));
+    }
+    if app.leaderboard.is_empty() {
+        text.push_str("Nadie lo ha resuelto aún\n");
+    }
+    if app.is_daily() {
+        if let Some(streak) = app.streak {
+            text.push_str(&format!("\nRacha: {} días (mejor {})", streak.current, streak.best));
+        }
+    } else if app.session.is_some() {
+        let best = app.personal_best.as_ref().map_or("-".to_string(), |r| format_millis(r.millis));
+        text.push_str(&format!("\nTu mejor tiempo: {best}"));
+    }
+
+    Paragraph::new(text)
+        .block(Block::default().title(if app.is_daily() {
+            format!("Ranking del día {n}x{n}", n = size.box_size().pow(2))
+        } else {
+            format!("Ranking {n}x{n} {difficulty:?}", n = size.box_size().pow(2))
+        }).borders(Borders::ALL))
+}
+
+/// The room's racers and their progress; `stopped` if it is no longer followed.
+fn race_widget(race: &RaceStatus, stopped: bool) -> Paragraph<'static> {
+    let mut text = match &race.state {
+        RaceState::Waiting => format!("Anfitrión: {}\nEsperando a que empiece\n\n", race.host),
+        RaceState::Running => "¡En carrera!\n\n".to_string(),
+        RaceState::Finished { winner } => format!("¡Gana {winner}!\n\n"),
+    };
+
+    for racer in &race.racers {
+        let done = (racer.filled * RACE_BAR_WIDTH).checked_div(race.to_fill).unwrap_or(0);
+        text.push_str(&format!(
+            "{:<18} {} fallos\n[{}{}] {}/{}\n",
+            racer.username,
+            racer.mistakes,
+            "#".repeat(done),
+            ".".repeat(RACE_BAR_WIDTH - done),
+            racer.filled,
+            race.to_fill,
+        ));
+    }
+    if stopped {
+        text.push_str("\nSin actualizaciones: se perdió el contacto con la sala");
+    }
+
+    Paragraph::new(text)
+        .block(Block::default().title(format!(
+            "Carrera - sala {} ({n}x{n} {:?})",
+            race.room,
+            race.difficulty,
+            n = race.size.box_size().pow(2),
+        )).borders(Borders::ALL))
+}
+
+/// Long-polls `race` in the background, sending every new status until the race is over.
+fn follow_race(client: RPCClient, race: &RaceStatus) -> (mpsc::UnboundedReceiver<RaceStatus>, JoinHandle<()>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let room = race.room;
+    let mut version = race.version;
+
+    let poller = tokio::spawn(async move {
+        let mut failures = 0;
+        loop {
+            let status = match client.race_status(room, version).await {
+                Ok(status) => status,
+                Err(err) => {
+                    failures += 1;
+                    if !keep_following(&format!("Sala {room}"), err, failures).await {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            failures = 0;
+            if status.version == version {
+                continue;
+            }
+            version = status.version;
+            let finished = matches!(status.state, RaceState::Finished { .. });
+            if tx.send(status).is_err() || finished {
+                return;
+            }
+        }
+    });
+    (rx, poller)
+}
+
+/// Long-polls the events of game `id` in the background, from the oldest the server keeps, and
+/// sends each batch as it arrives.
+fn follow_events(client: RPCClient, id: GameId) -> (mpsc::UnboundedReceiver<Vec<GameEvent>>, JoinHandle<()>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let poller = tokio::spawn(async move {
+        let mut since = 0;
+        let mut failures = 0;
+        loop {
+            let events = match client.next_events(id, since).await {
+                Ok(events) => events,
+                Err(err) => {
+                    failures += 1;
+                    if !keep_following(&format!("Partida {id}"), err, failures).await {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            failures = 0;
+            let Some(last) = events.last() else { continue };
+            since = last.seq;
+            if tx.send(events).is_err() {
+                return;
+            }
+        }
+    });
+    (rx, poller)
+}
+
+/// Logs why a long-poll of `what` failed and waits before the next try, longer each time. After
+/// `FOLLOW_RETRIES` failures in a row gives up and returns false.
+async fn keep_following(what: &str, err: anyhow::Error, failures: u32) -> bool {
+    if failures >= FOLLOW_RETRIES {
+        tracing::error!(target: "cliente", "{what}: {err}; se deja de seguir");
+        return false;
+    }
+    let delay = Duration::from_secs(1 << failures);
+    tracing::warn!(target: "cliente", "{what}: {err}; se reintenta en {} s", delay.as_secs());
+    time::sleep(delay).await;
+    true
+}
+
+/// `m:ss.d` for a solve time.
+fn format_millis(millis: u64) -> String {
+    format!("{}:{:02}.{}", millis / 60_000, millis / 1000 % 60, millis / 100 % 10)
+}
+
+/// `m:ss` for the clock of a game in progress.
+fn format_clock(millis: u64) -> String {
+    format!("{}:{:02}", millis / 60_000, millis / 1000 % 60)
+}
+
+fn contains(area: Rect, x: u16, y: u16) -> bool {
+    (area.left()..area.right()).contains(&x) && (area.top()..area.bottom()).contains(&y)
+}
+
+/// Digits 1 to `size` to click on, and a button that clears the cell.
+fn palette_widget(size: usize) -> Paragraph<'static> {
+    let digits: String = (1..=size as u8).filter_map(digit_char).flat_map(|d| [' ', d]).collect();
+    Paragraph::new(format!("{digits}  Borrar"))
+        .block(Block::default().title("Números").borders(Borders::ALL))
+}
+
+/// Value clicked at column `x`, row `y` of the palette drawn by [`palette_widget`] in `area`: a
+/// digit, or 0 for "Borrar".
+fn palette_value(area: Rect, size: usize, x: u16, y: u16) -> Option<u8> {
+    let inner = Block::default().borders(Borders::ALL).inner(area);
+    if !contains(inner, x, y) {
+        return None;
+    }
+    let column = (x - inner.x) as usize;
+    match column {
+        c if c < 2 * size => Some((c / 2) as u8 + 1),
+        c if (2 * size + 2..2 * size + 8).contains(&c) => Some(0),
+        _ => None,
+    }
+}
+
+/// The grid of `game` as laid out in the main view, without the highlight; used to find cells.
+fn board_grid(game: &Game) -> Grid<'_> {
+    sudoku_widget(game, None, 0, false).block(Block::default().borders(Borders::ALL))
+}
+
+fn no_sudoku() -> Paragraph<'static> {
+    Paragraph::new("No hay sudoku")
+        .block(Block::default().title("Sudoku").borders(Borders::ALL))
+        .alignment(Alignment::Center)
+}
+
+/// The game's board, with the cell under `cursor` and its row, column and box highlighted, and
+/// underneath its state, the `elapsed` time and the mistakes. A solved game shows its final time
+/// instead, and whether it went into the leaderboard (`ranked`).
+pub fn sudoku_widget(game: &Game, cursor: Option<(usize, usize)>, elapsed: u64, ranked: bool) -> Grid<'_> {
+    let mut footer = Vec::new();
+    let mut grid = Grid::new(&game.sudoku.board, &game.puzzle).notes(&game.notes).cursor(cursor);
+    if let Some(coop) = &game.coop {
+        grid = grid.authors(&coop.filled_by, &PLAYER_COLORS);
+        footer.push(player_legend(coop));
+    }
+    footer.push(match game.sudoku.state {
+        SudokuState::Solved => Line::styled(
+            format!("¡Resuelto en {}!{}", format_millis(elapsed), if ranked { "" } else { " (fuera del ranking)" }),
+            Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+        ),
+        ref state => Line::from(format!("{state:?}")),
+    });
+    footer.push(Line::from(format!("⏱ {}   Errores: {}", format_clock(elapsed), game.mistakes)));
+    grid.footer(footer)
+}
+
+/// Names of the players of a cooperative game, each in their color.
+fn player_legend(coop: &Coop) -> Line<'static> {
+    Line::from(coop.players.iter().enumerate().map(|(i, name)| {
+        Span::styled(format!(" {name} "), Style::default().fg(PLAYER_COLORS[i % PLAYER_COLORS.len()]))
+    }).collect::<Vec<_>>())
+}
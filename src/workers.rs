@@ -0,0 +1,31 @@
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Runs blocking jobs (puzzle generation, password hashing, database access) on tokio's blocking
+/// threads, at most `size` at a time, so they never occupy the async workers that drive the
+/// connections.
+#[derive(Clone)]
+pub struct BlockingPool {
+    permits: Arc<Semaphore>,
+}
+
+impl BlockingPool {
+    pub fn new(size: usize) -> Self {
+        BlockingPool { permits: Arc::new(Semaphore::new(size.max(1))) }
+    }
+
+    /// Waits for a free slot and runs `job` on it.
+    pub async fn run<F, R>(&self, job: F) -> R
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let permit = self.permits.clone().acquire_owned().await.expect("el pool no se cierra");
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            job()
+        })
+        .await
+        .expect("la tarea bloqueante entró en pánico")
+    }
+}